@@ -1,6 +1,71 @@
+-- The schema of a new database. A database created from an earlier version of this file is
+-- brought up to date by running the scripts in migrations/ it doesn't have yet, in order.
+
+CREATE TABLE users (
+	user_id UUID PRIMARY KEY,
+	email TEXT NOT NULL UNIQUE,
+	password_hash TEXT NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
 CREATE TABLE responses (
 	response_id UUID PRIMARY KEY,
 	user_id UUID,
+	test_id TEXT,
+	series_id UUID NOT NULL,
+	target_id UUID,
+	start_time TIMESTAMP NOT NULL DEFAULT NOW(),
 	submit_time TIMESTAMP NOT NULL,
+	content JSON NOT NULL,
+	paradata JSON NOT NULL DEFAULT '{}',
+	quality JSON,
+	last_page INT NOT NULL DEFAULT 0,
+	completed BOOLEAN NOT NULL DEFAULT FALSE,
+	consent_withdrawn BOOLEAN NOT NULL DEFAULT FALSE,
+	withdrawal_hash TEXT UNIQUE,
+	retest_hash TEXT UNIQUE,
+	compatibility_hash TEXT UNIQUE,
+	text_removed BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX responses_test_id ON responses (test_id);
+
+CREATE TABLE archived_responses (
+	LIKE responses INCLUDING DEFAULTS,
+	archived_time TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE compatibility_links (
+	from_id UUID NOT NULL,
+	to_id UUID NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW(),
+	PRIMARY KEY (from_id, to_id)
+);
+
+CREATE TABLE shares (
+	share_id UUID PRIMARY KEY,
+	response_id UUID NOT NULL,
+	summary BOOLEAN NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW(),
+	expires TIMESTAMP,
+	revoked TIMESTAMP
+);
+
+CREATE TABLE aggregate_releases (
+	release_id SERIAL PRIMARY KEY,
+	test_id TEXT NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW(),
+	mechanism TEXT NOT NULL,
+	epsilon FLOAT8 NOT NULL,
+	delta FLOAT8 NOT NULL,
 	content JSON NOT NULL
-);
\ No newline at end of file
+);
+
+CREATE TABLE withdrawals (
+	withdrawal_id SERIAL PRIMARY KEY,
+	response_id UUID NOT NULL,
+	test_id TEXT,
+	action TEXT NOT NULL,
+	via TEXT NOT NULL,
+	time TIMESTAMP NOT NULL DEFAULT NOW()
+);
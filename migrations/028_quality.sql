@@ -0,0 +1,8 @@
+-- Careless responding indices need the time a response was started. Responses from before this
+-- only have the time of their last submission, which is the closest there is.
+BEGIN;
+ALTER TABLE responses ADD COLUMN start_time TIMESTAMP;
+UPDATE responses SET start_time = submit_time;
+ALTER TABLE responses ALTER COLUMN start_time SET NOT NULL, ALTER COLUMN start_time SET DEFAULT NOW();
+ALTER TABLE responses ADD COLUMN quality JSON;
+COMMIT;
@@ -0,0 +1,3 @@
+BEGIN;
+ALTER TABLE responses ADD COLUMN paradata JSON NOT NULL DEFAULT '{}';
+COMMIT;
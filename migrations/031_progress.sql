@@ -0,0 +1,9 @@
+-- Before this the TIPI was the only test, and its last page always asked for consent, so the
+-- responses that answered it are the completed ones.
+BEGIN;
+ALTER TABLE responses ADD COLUMN test_id TEXT;
+UPDATE responses SET test_id = 'tipi';
+ALTER TABLE responses ADD COLUMN last_page INT NOT NULL DEFAULT 0;
+ALTER TABLE responses ADD COLUMN completed BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE responses SET completed = TRUE WHERE content::jsonb ? 'consent';
+COMMIT;
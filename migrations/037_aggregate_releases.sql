@@ -0,0 +1,11 @@
+BEGIN;
+CREATE TABLE aggregate_releases (
+	release_id SERIAL PRIMARY KEY,
+	test_id TEXT NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW(),
+	mechanism TEXT NOT NULL,
+	epsilon FLOAT8 NOT NULL,
+	delta FLOAT8 NOT NULL,
+	content JSON NOT NULL
+);
+COMMIT;
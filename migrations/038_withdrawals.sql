@@ -0,0 +1,12 @@
+BEGIN;
+ALTER TABLE responses ADD COLUMN consent_withdrawn BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE responses ADD COLUMN withdrawal_hash TEXT UNIQUE;
+CREATE TABLE withdrawals (
+	withdrawal_id SERIAL PRIMARY KEY,
+	response_id UUID NOT NULL,
+	test_id TEXT,
+	action TEXT NOT NULL,
+	via TEXT NOT NULL,
+	time TIMESTAMP NOT NULL DEFAULT NOW()
+);
+COMMIT;
@@ -0,0 +1,8 @@
+-- The archive copies the columns responses have at this point; later scripts add theirs to both.
+BEGIN;
+ALTER TABLE responses ADD COLUMN text_removed BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE TABLE archived_responses (
+	LIKE responses INCLUDING DEFAULTS,
+	archived_time TIMESTAMP NOT NULL DEFAULT NOW()
+);
+COMMIT;
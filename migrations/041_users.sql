@@ -0,0 +1,8 @@
+BEGIN;
+CREATE TABLE users (
+	user_id UUID PRIMARY KEY,
+	email TEXT NOT NULL UNIQUE,
+	password_hash TEXT NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW()
+);
+COMMIT;
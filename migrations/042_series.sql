@@ -0,0 +1,11 @@
+-- Each earlier response starts a series of its own, as a new response without an account does.
+BEGIN;
+ALTER TABLE responses ADD COLUMN series_id UUID;
+UPDATE responses SET series_id = response_id;
+ALTER TABLE responses ALTER COLUMN series_id SET NOT NULL;
+ALTER TABLE responses ADD COLUMN retest_hash TEXT UNIQUE;
+ALTER TABLE archived_responses ADD COLUMN series_id UUID;
+UPDATE archived_responses SET series_id = response_id;
+ALTER TABLE archived_responses ALTER COLUMN series_id SET NOT NULL;
+ALTER TABLE archived_responses ADD COLUMN retest_hash TEXT;
+COMMIT;
@@ -0,0 +1,4 @@
+BEGIN;
+ALTER TABLE responses ADD COLUMN target_id UUID;
+ALTER TABLE archived_responses ADD COLUMN target_id UUID;
+COMMIT;
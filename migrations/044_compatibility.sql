@@ -0,0 +1,10 @@
+BEGIN;
+ALTER TABLE responses ADD COLUMN compatibility_hash TEXT UNIQUE;
+ALTER TABLE archived_responses ADD COLUMN compatibility_hash TEXT;
+CREATE TABLE compatibility_links (
+	from_id UUID NOT NULL,
+	to_id UUID NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW(),
+	PRIMARY KEY (from_id, to_id)
+);
+COMMIT;
@@ -0,0 +1,10 @@
+BEGIN;
+CREATE TABLE shares (
+	share_id UUID PRIMARY KEY,
+	response_id UUID NOT NULL,
+	summary BOOLEAN NOT NULL,
+	created TIMESTAMP NOT NULL DEFAULT NOW(),
+	expires TIMESTAMP,
+	revoked TIMESTAMP
+);
+COMMIT;
@@ -0,0 +1,3 @@
+BEGIN;
+CREATE INDEX responses_test_id ON responses (test_id);
+COMMIT;
@@ -0,0 +1,172 @@
+use rocket::serde::Serialize;
+use serde_json::Value;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::database;
+use crate::irt::eap;
+use crate::signing;
+use crate::tests::{get_test, QuestionContent, Test};
+use crate::util::format_timestamp;
+
+// Subject access reports: everything stored about a response, for the respondent it belongs
+// to. Answers are shown with the question texts from the test definition, next to the stored
+// data as is, and with the scores and quality indices derived from them. Reports are reached
+// through a signed token, handed out with the feedback or by an administrator.
+
+const TOKEN_PURPOSE: &str = "access";
+
+/// A response's row as stored.
+pub struct StoredResponse {
+    pub response_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub test_id: Option<String>,
+    pub start_time: f64,
+    pub submit_time: f64,
+    pub last_page: usize,
+    pub completed: bool,
+    pub consent_withdrawn: bool,
+    pub content: Value,
+    pub paradata: Value,
+    pub quality: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AnswerReport {
+    pub id: String,
+    pub question: String,
+    pub answer: Option<String>,
+    pub stored: Value,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScoreReport {
+    pub scale: String,
+    pub mean: f64,
+    pub theta: Option<f64>,
+    pub theta_se: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WithdrawalReport {
+    pub time: String,
+    pub action: String,
+    pub via: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccessReport {
+    pub response_id: String,
+    pub user_id: Option<String>,
+    pub test_id: Option<String>,
+    pub test_name: Option<String>,
+    pub start_time: String,
+    pub submit_time: String,
+    /// 1-based number of the furthest page reached.
+    pub last_page: usize,
+    pub completed: bool,
+    pub consent_withdrawn: bool,
+    pub answers: Vec<AnswerReport>,
+    pub scores: Vec<ScoreReport>,
+    pub quality: Option<Value>,
+    pub paradata: Value,
+    pub content: Value,
+    pub withdrawals: Vec<WithdrawalReport>,
+}
+
+pub fn token(response_id: Uuid) -> String {
+    signing::sign(TOKEN_PURPOSE, &response_id.to_string())
+}
+
+pub fn verify(response_id: Uuid, token: &str) -> bool {
+    signing::verify(TOKEN_PURPOSE, &response_id.to_string(), token)
+}
+
+fn answers(test: &Test, content: &Value) -> Vec<AnswerReport> {
+    test.questions().into_iter().map(|(q, prompt)| {
+        let stored = &content[&q.id];
+        let text = |v: &Value| v.as_str().map(String::from);
+        let answer = if stored.is_null() { None } else if stored["removed"] == true {
+            Some("Removed after the retention period".into())
+        } else {
+            match &q.content {
+                QuestionContent::McQuestion { expected: Some(_), .. } =>
+                    Some((if stored["passed"] == true { "Passed" } else { "Failed" }).into()),
+                QuestionContent::McQuestionVert { .. } if stored["nom"] == "Other" =>
+                    Some(format!("Other: {}", stored["answer"].as_str().unwrap_or(""))),
+                QuestionContent::McQuestion { .. } | QuestionContent::McQuestionVert { .. } => text(&stored["nom"]),
+                QuestionContent::CheckboxQuestion { .. } =>
+                    Some((if stored["checked"] == true { "Checked" } else { "Not checked" }).into()),
+                QuestionContent::TextAreaQuestion => text(&stored["answer"]),
+                QuestionContent::Header { .. } | QuestionContent::Paragraph { .. } | QuestionContent::AlignText { .. } => None,
+            }
+        };
+        let question = match &q.content {
+            QuestionContent::CheckboxQuestion { text } => text.clone(),
+            _ => prompt.to_string(),
+        };
+        AnswerReport { id: q.id.clone(), question, answer, stored: stored.clone() }
+    }).collect()
+}
+
+fn scores(test: &Test, content: &Value) -> Vec<ScoreReport> {
+    test.scales.iter().filter_map(|scale| {
+        let mean = scale.mean(content)?;
+        let estimate = test.calibration.as_ref()
+            .and_then(|cal| cal.scales.get(&scale.id))
+            .map(|items| eap(items, &scale.keyed_answers(content)));
+        Some(ScoreReport {
+            scale: scale.name.clone(),
+            mean,
+            theta: estimate.map(|e| e.theta),
+            theta_se: estimate.map(|e| e.se),
+        })
+    }).collect()
+}
+
+pub async fn report(response_id: Uuid, conn: &mut PoolConnection<Postgres>) -> Option<AccessReport> {
+    let stored = database::get_stored_response(response_id, conn).await?;
+    let withdrawals = database::get_withdrawals(response_id, conn).await.into_iter()
+        .map(|(time, action, via)| WithdrawalReport { time: format_timestamp(time), action, via })
+        .collect();
+    let test = stored.test_id.as_deref().and_then(get_test);
+    let answers = test.map_or(vec![], |test| answers(test, &stored.content));
+    let scores = test.map_or(vec![], |test| scores(test, &stored.content));
+    Some(AccessReport {
+        response_id: stored.response_id.to_string(),
+        user_id: stored.user_id.map(|id| id.to_string()),
+        test_name: test.map(|test| test.name.clone()),
+        test_id: stored.test_id,
+        start_time: format_timestamp(stored.start_time),
+        submit_time: format_timestamp(stored.submit_time),
+        last_page: stored.last_page + 1,
+        completed: stored.completed,
+        consent_withdrawn: stored.consent_withdrawn,
+        answers,
+        scores,
+        quality: stored.quality,
+        paradata: stored.paradata,
+        content: stored.content,
+        withdrawals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::withdrawal;
+    use super::*;
+
+    #[test]
+    fn tokens_open_only_their_response() {
+        let token = token(Uuid::from_u128(1));
+        assert!(verify(Uuid::from_u128(1), &token));
+        assert!(!verify(Uuid::from_u128(2), &token));
+        assert!(!verify(Uuid::from_u128(1), ""));
+        // A withdrawal link's signature doesn't give access to the data.
+        assert!(!verify(Uuid::from_u128(1), &withdrawal::link_signature(Uuid::from_u128(1))));
+    }
+}
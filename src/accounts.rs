@@ -0,0 +1,74 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::database;
+
+// Optional user accounts. Respondents don't need one to take a test, but responses taken while
+// logged in are linked to the user, who can then find their results again. Passwords are hashed
+// with argon2; the session is a private (encrypted and authenticated) cookie holding the user id,
+// so release builds need Rocket's `secret_key` to be configured.
+
+const SESSION_COOKIE: &str = "userId";
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The logged-in user; forwards if nobody is logged in.
+pub struct User {
+    pub user_id: Uuid,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for User {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let user_id = req.cookies().get_private(SESSION_COOKIE).and_then(|cookie| cookie.value().parse().ok());
+        match user_id {
+            Some(user_id) => Outcome::Success(User { user_id }),
+            None => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Creates an account, or says why it can't be created.
+pub async fn register(email: &str, password: &str, conn: &mut PoolConnection<Postgres>) -> Result<Uuid, &'static str> {
+    let email = normalize_email(email);
+    if !email.contains('@') {
+        return Err("Please enter a valid email address.");
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err("Your password must be at least 8 characters long.");
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt).unwrap().to_string();
+    let user_id = Uuid::new_v4();
+    if database::create_user(user_id, &email, &hash, conn).await {
+        Ok(user_id)
+    } else {
+        Err("There already is an account with this email address.")
+    }
+}
+
+/// The user with this email and password, if any.
+pub async fn authenticate(email: &str, password: &str, conn: &mut PoolConnection<Postgres>) -> Option<Uuid> {
+    let (user_id, hash) = database::get_user_by_email(&normalize_email(email), conn).await?;
+    let hash = PasswordHash::new(&hash).unwrap();
+    Argon2::default().verify_password(password.as_bytes(), &hash).ok()?;
+    Some(user_id)
+}
+
+pub fn log_in(cookies: &CookieJar<'_>, user_id: Uuid) {
+    cookies.add_private(Cookie::new(SESSION_COOKIE, user_id.to_string()));
+}
+
+pub fn log_out(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::from(SESSION_COOKIE));
+}
@@ -0,0 +1,95 @@
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// Access to the administration pages. They are opened with the `admin_token` set in Rocket.toml,
+// sent as a bearer token or entered once on the admin login page, which keeps it in a private
+// cookie. Without a configured token, the administration pages can't be opened.
+
+const ADMIN_COOKIE: &str = "adminToken";
+
+/// The administration settings in Rocket.toml, managed by the server.
+#[derive(Deserialize, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct AdminConfig {
+    admin_token: Option<String>,
+}
+
+impl AdminConfig {
+    /// Whether `given` is the configured admin token. The digests are compared so that the time
+    /// taken doesn't depend on how much of the token is right.
+    pub fn accepts(&self, given: &str) -> bool {
+        match &self.admin_token {
+            Some(token) if !token.is_empty() => Sha256::digest(given.as_bytes()) == Sha256::digest(token.as_bytes()),
+            _ => false,
+        }
+    }
+}
+
+/// An administrator, authenticated by the configured admin token; fails with 401 otherwise.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let config = req.rocket().state::<AdminConfig>().unwrap();
+        let bearer = req.headers().get_one("Authorization").and_then(|header| header.strip_prefix("Bearer "));
+        let cookie = req.cookies().get_private(ADMIN_COOKIE);
+        match bearer.or_else(|| cookie.as_ref().map(|cookie| cookie.value())) {
+            Some(given) if config.accepts(given) => Outcome::Success(Admin),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+pub fn log_in(cookies: &CookieJar<'_>, token: &str) {
+    cookies.add_private(Cookie::new(ADMIN_COOKIE, token.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+    use super::*;
+
+    fn config(token: Option<&str>) -> AdminConfig {
+        AdminConfig { admin_token: token.map(String::from) }
+    }
+
+    #[test]
+    fn accepts_only_the_configured_token() {
+        assert!(config(Some("s3cret")).accepts("s3cret"));
+        assert!(!config(Some("s3cret")).accepts("s3cre"));
+        assert!(!config(Some("s3cret")).accepts(""));
+        // Without a token, nothing opens the administration pages.
+        assert!(!config(None).accepts(""));
+        assert!(!config(Some("")).accepts(""));
+    }
+
+    #[get("/admin")]
+    fn guarded(_admin: Admin) -> &'static str {
+        "ok"
+    }
+
+    #[get("/login/<token>")]
+    fn login(token: &str, cookies: &CookieJar<'_>) {
+        log_in(cookies, token);
+    }
+
+    #[test]
+    fn guard_needs_the_token() {
+        let rocket = rocket::build().manage(config(Some("s3cret"))).mount("/", routes![guarded, login]);
+        let client = Client::untracked(rocket).unwrap();
+        assert_eq!(client.get("/admin").dispatch().status(), Status::Unauthorized);
+        let wrong = Header::new("Authorization", "Bearer guess");
+        assert_eq!(client.get("/admin").header(wrong).dispatch().status(), Status::Unauthorized);
+        let right = Header::new("Authorization", "Bearer s3cret");
+        assert_eq!(client.get("/admin").header(right).dispatch().status(), Status::Ok);
+
+        let cookies = client.get("/login/s3cret").dispatch().cookies().iter().cloned().collect::<Vec<_>>();
+        assert_eq!(client.get("/admin").cookies(cookies).dispatch().status(), Status::Ok);
+    }
+}
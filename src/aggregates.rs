@@ -0,0 +1,314 @@
+use rand::Rng;
+use rand::distributions::Open01;
+use rocket::futures::StreamExt;
+use rocket::serde::{Deserialize, Serialize};
+use sqlx::{Acquire, Postgres};
+use sqlx::pool::PoolConnection;
+use crate::database;
+use crate::dataset::{Cell, Column, Dataset, ExportFilter, Record, Source};
+use crate::tests::Test;
+
+// Differentially private statistics over the consented responses to a test: respondent counts,
+// scale means and histograms of scale means, overall and by each demographic question. They are
+// published as releases, each of which spends part of the test's privacy budget; once the budget
+// is spent, no further releases can be made. The group categories come from the test definition,
+// never from the data, so empty groups are reported (with noise) like any other.
+//
+// Each release splits its epsilon and delta evenly over four queries: group sizes, the number
+// of respondents with a score on each scale, the sums of those scores, and the histograms.
+// A respondent counts towards the overall group and one group per demographic question. The
+// Gaussian mechanism's classic calibration only holds for an epsilon below 1 per query, so it
+// can only be used for releases with an epsilon below `QUERIES`.
+
+pub const TOTAL_EPSILON: f64 = 10.0;
+pub const TOTAL_DELTA: f64 = 1e-5;
+pub const DEFAULT_EPSILON: f64 = 1.0;
+pub const DEFAULT_DELTA: f64 = 1e-6;
+pub const QUERIES: f64 = 4.0;
+// Means of groups with fewer (noisy) respondents than this are too noisy to be worth showing.
+const MIN_COUNT: f64 = 10.0;
+const BINS_PER_CATEGORY: usize = 2;
+
+#[derive(FromFormField, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "rocket::serde")]
+pub enum Mechanism {
+    Laplace,
+    Gaussian,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Bin {
+    pub from: f64,
+    pub to: f64,
+    pub count: f64,
+    /// The count relative to the largest bin of the histogram, for drawing it.
+    pub share: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ScaleAggregate {
+    pub scale: String,
+    pub mean: Option<f64>,
+    pub histogram: Vec<Bin>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct GroupAggregate {
+    pub label: String,
+    pub count: f64,
+    pub scales: Vec<ScaleAggregate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Aggregates {
+    pub groups: Vec<GroupAggregate>,
+}
+
+impl Mechanism {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Laplace => "laplace",
+            Mechanism::Gaussian => "gaussian",
+        }
+    }
+
+    // Noise for one query with the given L1 and L2 sensitivities. The Gaussian mechanism uses the
+    // classic calibration, which holds for epsilon below 1 per query, and refuses larger ones.
+    // The uniform samples are drawn from open intervals, since the logarithms are infinite at the
+    // ends.
+    fn noise(&self, l1: f64, l2: f64, epsilon: f64, delta: f64) -> Result<f64, String> {
+        let mut rng = rand::thread_rng();
+        match self {
+            Mechanism::Laplace => {
+                let u: f64 = rng.sample::<f64, _>(Open01) - 0.5;
+                Ok(-(l1 / epsilon) * u.signum() * (1.0 - 2.0 * u.abs()).ln())
+            }
+            Mechanism::Gaussian if epsilon >= 1.0 => {
+                Err("The Gaussian mechanism needs an epsilon below 1 per query".into())
+            }
+            Mechanism::Gaussian => {
+                let sigma = l2 * (2.0 * (1.25 / delta).ln()).sqrt() / epsilon;
+                let (u1, u2): (f64, f64) = (rng.sample(Open01), rng.gen());
+                Ok(sigma * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos())
+            }
+        }
+    }
+}
+
+struct Group {
+    label: String,
+    // The demographic column and the code respondents in the group have there, or `None` for
+    // the overall group and the respondents who did not answer.
+    filter: Option<(usize, Option<i64>)>,
+}
+
+fn groups(columns: &[Column], demographics: &[usize]) -> Vec<Group> {
+    let mut groups = vec![Group { label: "All respondents".into(), filter: None }];
+    for i in demographics {
+        let column = &columns[*i];
+        for (code, label) in &column.value_labels {
+            groups.push(Group { label: format!("{}: {}", column.label, label), filter: Some((*i, Some(*code))) });
+        }
+        groups.push(Group { label: format!("{}: not answered", column.label), filter: Some((*i, None)) });
+    }
+    groups
+}
+
+fn in_group(group: &Group, row: &[Cell]) -> bool {
+    match group.filter {
+        None => true,
+        Some((i, code)) => match &row[i] {
+            Cell::Integer(n) => code == Some(*n),
+            Cell::Boolean(b) => code == Some(*b as i64),
+            _ => code.is_none(),
+        },
+    }
+}
+
+// A histogram of scale means from 1 to `categories`, with the count in each bin made noisy by
+// `noisy_count`. A scale with a single category has no range to divide into bins, so its
+// histogram is empty.
+fn histogram(scores: &[f64], categories: f64, noisy_count: impl Fn(usize) -> Result<f64, String>) -> Result<Vec<Bin>, String> {
+    let bins = BINS_PER_CATEGORY * (categories as usize).saturating_sub(1);
+    if bins == 0 {
+        return Ok(vec![]);
+    }
+    let width = 1.0 / BINS_PER_CATEGORY as f64;
+    let mut counts = vec![0; bins];
+    for x in scores {
+        counts[(((x - 1.0) / width) as usize).min(bins - 1)] += 1;
+    }
+    let counts = counts.into_iter().map(noisy_count).collect::<Result<Vec<f64>, String>>()?;
+    let largest = counts.iter().cloned().fold(1.0, f64::max);
+    Ok(counts.iter().enumerate().map(|(b, count)| Bin {
+        from: 1.0 + b as f64 * width,
+        to: 1.0 + (b + 1) as f64 * width,
+        count: *count,
+        share: count / largest,
+    }).collect())
+}
+
+/// Computes noisy aggregates of the records with a total budget of `epsilon` and `delta`.
+pub fn compute(test: &Test, records: &[Record], mechanism: Mechanism, epsilon: f64, delta: f64) -> Result<Aggregates, String> {
+    let mut dataset = Dataset::new(test, false);
+    dataset.columns.retain(|column| match &column.source {
+        Source::ScaleMean(_) => true,
+        _ => column.question().is_some_and(|id| test.demographics.iter().any(|d| d == id))
+            && !column.value_labels.is_empty(),
+    });
+    let demographics: Vec<usize> = (0..dataset.columns.len())
+        .filter(|i| !matches!(dataset.columns[*i].source, Source::ScaleMean(_)))
+        .collect();
+    let scales: Vec<(usize, f64, &str)> = (0..dataset.columns.len()).filter_map(|i| match &dataset.columns[i].source {
+        Source::ScaleMean(id) => {
+            let scale = test.scale(id).unwrap();
+            Some((i, scale.categories as f64, scale.name.as_str()))
+        }
+        _ => None,
+    }).collect();
+    let rows: Vec<Vec<Cell>> = records.iter().map(|record| dataset.row(record)).collect();
+
+    let (epsilon, delta) = (epsilon / QUERIES, delta / QUERIES);
+    let memberships = 1.0 + demographics.len() as f64;
+    let contributions = memberships * scales.len() as f64;
+    let max_range = scales.iter().map(|(_, categories, _)| categories - 1.0).fold(0.0, f64::max);
+    let noisy_count = |count: usize, l1: f64, l2: f64| {
+        Ok::<f64, String>((count as f64 + mechanism.noise(l1, l2, epsilon, delta)?).round().max(0.0))
+    };
+
+    let groups = groups(&dataset.columns, &demographics).into_iter().map(|group| {
+        let members: Vec<&Vec<Cell>> = rows.iter().filter(|row| in_group(&group, row)).collect();
+        let scales = scales.iter().map(|(i, categories, name)| {
+            let scores: Vec<f64> = members.iter().filter_map(|row| match row[*i] {
+                Cell::Float(x) => Some(x.clamp(1.0, *categories)),
+                _ => None,
+            }).collect();
+            let count = noisy_count(scores.len(), contributions, contributions.sqrt())?;
+            let sum = scores.iter().map(|x| x - 1.0).sum::<f64>()
+                + mechanism.noise(contributions * max_range, contributions.sqrt() * max_range, epsilon, delta)?;
+            Ok(ScaleAggregate {
+                scale: name.to_string(),
+                mean: if count >= MIN_COUNT { Some((1.0 + sum / count).clamp(1.0, *categories)) } else { None },
+                histogram: histogram(&scores, *categories, |count| noisy_count(count, contributions, contributions.sqrt()))?,
+            })
+        }).collect::<Result<Vec<_>, String>>()?;
+        Ok(GroupAggregate {
+            label: group.label,
+            count: noisy_count(members.len(), memberships, memberships.sqrt())?,
+            scales,
+        })
+    }).collect::<Result<Vec<_>, String>>()?;
+    Ok(Aggregates { groups })
+}
+
+// Checks the parameters of a release and returns the delta it spends, which is none for the
+// Laplace mechanism.
+fn spent_delta(mechanism: Mechanism, epsilon: f64, delta: f64) -> Result<f64, String> {
+    let delta = if mechanism == Mechanism::Laplace { 0.0 } else { delta };
+    let valid_delta = delta > 0.0 && delta < 1.0;
+    if !epsilon.is_finite() || epsilon <= 0.0 || (mechanism == Mechanism::Gaussian && !valid_delta) {
+        return Err("Epsilon must be positive, and delta between 0 and 1 for the Gaussian mechanism".into());
+    }
+    if mechanism == Mechanism::Gaussian && epsilon >= QUERIES {
+        return Err(format!("The Gaussian mechanism needs an epsilon below {}; use the Laplace mechanism for more", QUERIES));
+    }
+    Ok(delta)
+}
+
+// Checks that a release fits in what is left of the budget after the releases made so far.
+fn within_budget(spent: (f64, f64), epsilon: f64, delta: f64) -> Result<(), String> {
+    let (spent_epsilon, spent_delta) = spent;
+    if spent_epsilon + epsilon > TOTAL_EPSILON || spent_delta + delta > TOTAL_DELTA {
+        return Err(format!(
+            "The release would exceed the privacy budget: {:.2} of {} epsilon and {:e} of {:e} delta are spent",
+            spent_epsilon, TOTAL_EPSILON, spent_delta, TOTAL_DELTA));
+    }
+    Ok(())
+}
+
+/// Computes and stores a new release of a test's aggregates, unless it would take the test over
+/// its privacy budget. The budget is checked and spent in one transaction that holds the test's
+/// budget lock, so that concurrent releases can't overspend it together.
+pub async fn publish(test: &Test, mechanism: Mechanism, epsilon: f64, delta: f64, conn: &mut PoolConnection<Postgres>) -> Result<(), String> {
+    let delta = spent_delta(mechanism, epsilon, delta)?;
+    let mut tx = conn.begin().await.unwrap();
+    database::lock_budget(&test.id, &mut tx).await;
+    within_budget(database::get_spent_budget(&test.id, &mut tx).await, epsilon, delta)?;
+    let filter = ExportFilter { consented: true, ..ExportFilter::default() };
+    let records: Vec<Record> = database::stream_records(test, &filter, &mut tx).collect().await;
+    let aggregates = compute(test, &records, mechanism, epsilon, delta)?;
+    database::insert_aggregate_release(&test.id, mechanism.name(), epsilon, delta,
+                                       serde_json::to_value(&aggregates).unwrap(), &mut tx).await;
+    tx.commit().await.unwrap();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 20000;
+
+    #[test]
+    fn laplace_noise_has_its_scale() {
+        let noise: Vec<f64> = (0..SAMPLES).map(|_| Mechanism::Laplace.noise(2.0, 0.0, 0.5, 0.0).unwrap()).collect();
+        // Laplace noise with scale b = l1 / epsilon has mean 0 and mean absolute value b.
+        let mean = noise.iter().sum::<f64>() / SAMPLES as f64;
+        let mean_abs = noise.iter().map(|x| x.abs()).sum::<f64>() / SAMPLES as f64;
+        assert!(mean.abs() < 0.2, "{}", mean);
+        assert!((mean_abs / 4.0 - 1.0).abs() < 0.05, "{}", mean_abs);
+    }
+
+    #[test]
+    fn gaussian_noise_has_its_calibration() {
+        let (l2, epsilon, delta) = (2.0, 0.5, 1e-6);
+        let sigma = l2 * (2.0 * (1.25f64 / delta).ln()).sqrt() / epsilon;
+        let noise: Vec<f64> = (0..SAMPLES).map(|_| Mechanism::Gaussian.noise(0.0, l2, epsilon, delta).unwrap()).collect();
+        let mean = noise.iter().sum::<f64>() / SAMPLES as f64;
+        let sd = (noise.iter().map(|x| x * x).sum::<f64>() / SAMPLES as f64).sqrt();
+        assert!(noise.iter().all(|x| x.is_finite()));
+        assert!(mean.abs() < 0.05 * sigma, "{}", mean);
+        assert!((sd / sigma - 1.0).abs() < 0.05, "{} vs {}", sd, sigma);
+        // The calibration doesn't hold from an epsilon of 1 on.
+        assert!(Mechanism::Gaussian.noise(0.0, l2, 1.0, delta).is_err());
+    }
+
+    #[test]
+    fn histograms_bin_the_scale_range() {
+        let exact = |count: usize| Ok(count as f64);
+        let bins = histogram(&[1.0, 1.4, 2.5, 5.0], 5.0, exact).unwrap();
+        assert_eq!(bins.len(), 8);
+        assert_eq!(bins.iter().map(|bin| bin.count).collect::<Vec<_>>(), [2.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!((bins[7].from, bins[7].to, bins[0].share), (4.5, 5.0, 1.0));
+        // A single category leaves nothing to bin.
+        assert!(histogram(&[1.0, 1.0], 1.0, exact).unwrap().is_empty());
+        assert!(histogram(&[1.0], 0.0, exact).unwrap().is_empty());
+    }
+
+    #[test]
+    fn release_parameters_are_checked() {
+        assert_eq!(spent_delta(Mechanism::Laplace, 1.0, 0.5), Ok(0.0));
+        assert_eq!(spent_delta(Mechanism::Gaussian, 1.0, 1e-6), Ok(1e-6));
+        for epsilon in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(spent_delta(Mechanism::Laplace, epsilon, 0.0).is_err());
+        }
+        for delta in [0.0, 1.0, -1e-6] {
+            assert!(spent_delta(Mechanism::Gaussian, 1.0, delta).is_err());
+        }
+        // Each query's share of epsilon must stay below 1 for the Gaussian calibration to hold.
+        assert!(spent_delta(Mechanism::Gaussian, QUERIES, 1e-6).is_err());
+        assert!(spent_delta(Mechanism::Laplace, QUERIES, 0.0).is_ok());
+    }
+
+    #[test]
+    fn releases_stay_within_the_budget() {
+        assert!(within_budget((0.0, 0.0), TOTAL_EPSILON, TOTAL_DELTA).is_ok());
+        assert!(within_budget((TOTAL_EPSILON - 1.0, 0.0), 1.0, 0.0).is_ok());
+        assert!(within_budget((TOTAL_EPSILON - 1.0, 0.0), 1.5, 0.0).is_err());
+        assert!(within_budget((0.0, TOTAL_DELTA), 1.0, 1e-9).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+use crate::irt::{eap, Estimate, GrmItem};
+
+// Computerized adaptive testing: items from a calibrated scale are given one at a time, each
+// chosen to be the most informative at the respondent's current trait estimate.
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Adaptive {
+    pub scale: String,
+    pub max_items: usize,
+    pub max_se: f64,
+}
+
+pub enum Step {
+    Administer(usize),
+    Done(Estimate),
+}
+
+impl Adaptive {
+    /// Decides what to do next given the answers so far (indexed like `items`).
+    pub fn next(&self, items: &[GrmItem], answers: &[Option<usize>]) -> Step {
+        let estimate = eap(items, answers);
+        let n_answered = answers.iter().filter(|a| a.is_some()).count();
+        if n_answered >= self.max_items || (n_answered > 0 && estimate.se <= self.max_se) {
+            return Step::Done(estimate);
+        }
+        let best = items.iter().enumerate()
+            .filter(|(i, _)| answers[*i].is_none())
+            .map(|(i, item)| (i, item.information(estimate.theta)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((i, _)) => Step::Administer(i),
+            None => Step::Done(estimate),
+        }
+    }
+}
+
+/// The administered sequence for a scale, as stored under the response's "cat" key.
+pub fn administered(resp: &Value, scale: &str) -> Vec<Value> {
+    resp["cat"][scale].as_array().cloned().unwrap_or_default()
+}
+
+/// Appends an administered item with the interim estimate after it was answered.
+pub fn record(resp: &Value, scale: &str, item: &str, estimate: Estimate) -> Value {
+    let mut cat = resp["cat"].as_object().cloned().unwrap_or_default();
+    let mut sequence = administered(resp, scale);
+    sequence.push(json!({"item": item, "theta": estimate.theta, "se": estimate.se}));
+    cat.insert(scale.into(), Value::Array(sequence));
+    Value::Object(cat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, discrimination: f64, location: f64) -> GrmItem {
+        GrmItem { id: id.into(), discrimination, thresholds: vec![location - 1.0, location, location + 1.0] }
+    }
+
+    #[test]
+    fn administers_the_most_informative_unanswered_item() {
+        let adaptive = Adaptive { scale: "scale".into(), max_items: 3, max_se: 0.0 };
+        let items = vec![item("far", 2.0, 3.0), item("weak", 0.5, 0.0), item("best", 2.0, 0.0)];
+        assert!(matches!(adaptive.next(&items, &[None, None, None]), Step::Administer(2)));
+        // Once the best item is answered, it isn't given again.
+        assert!(matches!(adaptive.next(&items, &[None, None, Some(2)]), Step::Administer(1) | Step::Administer(0)));
+    }
+
+    #[test]
+    fn a_broken_calibration_does_not_panic() {
+        let adaptive = Adaptive { scale: "scale".into(), max_items: 3, max_se: 0.0 };
+        let items = vec![item("nan", f64::NAN, 0.0), item("ok", 2.0, 0.0)];
+        assert!(matches!(adaptive.next(&items, &[None, None]), Step::Administer(_)));
+    }
+
+    #[test]
+    fn stops_at_the_item_limit_or_standard_error() {
+        let items = vec![item("a", 2.0, 0.0), item("b", 2.0, 0.0), item("c", 2.0, 0.0)];
+        let limited = Adaptive { scale: "scale".into(), max_items: 2, max_se: 0.0 };
+        assert!(matches!(limited.next(&items, &[Some(1), Some(2), None]), Step::Done(_)));
+        let precise = Adaptive { scale: "scale".into(), max_items: 3, max_se: 1.0 };
+        assert!(matches!(precise.next(&items, &[None, None, None]), Step::Administer(_)));
+        assert!(matches!(precise.next(&items, &[Some(1), None, None]), Step::Done(_)));
+        let exhausted = Adaptive { scale: "scale".into(), max_items: 5, max_se: 0.0 };
+        assert!(matches!(exhausted.next(&items, &[Some(1), Some(2), Some(1)]), Step::Done(_)));
+    }
+
+    #[test]
+    fn records_the_administered_sequence() {
+        let estimate = Estimate { theta: 0.5, se: 0.8 };
+        let cat = record(&json!({}), "scale", "a", estimate);
+        let resp = json!({ "cat": cat });
+        let cat = record(&resp, "scale", "b", estimate);
+        let items: Vec<&str> = cat["scale"].as_array().unwrap().iter().map(|step| step["item"].as_str().unwrap()).collect();
+        assert_eq!(items, ["a", "b"]);
+        assert!(administered(&json!({}), "scale").is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::BufWriter;
+use uuid::Uuid;
+use crate::access;
+use crate::codebook::Codebook;
+use crate::database;
+use crate::dataset::{Dataset, ExportFilter};
+use crate::export::parquet::write_parquet;
+use crate::irt::{Calibration, fit_grm};
+use crate::quality;
+use crate::release;
+use crate::retention::{self, Policy};
+use crate::tests::{self, Test};
+
+// Offline maintenance commands, run as `survey-data <command> <args...>` instead of starting
+// the server.
+
+fn usage() {
+    eprintln!("usage: survey-data calibrate <test> [--exclude-flagged]");
+    eprintln!("       survey-data assess-quality <test>");
+    eprintln!("       survey-data export-parquet <test> <file> [--from <date>] [--to <date>] [--completed]");
+    eprintln!("                                  [--consented] [--exclude-flagged] [--text]");
+    eprintln!("       survey-data codebook <test> <md|xml>");
+    eprintln!("       survey-data release <test> [<k>]");
+    eprintln!("       survey-data access-link <response>");
+    eprintln!("       survey-data retention [--dry-run]");
+}
+
+fn get_test(id: &str) -> &'static Test {
+    tests::get_test(id).unwrap_or_else(|| panic!("No test with id {:?}", id))
+}
+
+pub async fn calibrate(test: &Test, exclude_flagged: bool) {
+    let pool = database::connect().await;
+    let contents = database::get_all_contents(&test.id, exclude_flagged, &mut pool.acquire().await.unwrap()).await;
+    let mut calibration = Calibration::default();
+    for scale in &test.scales {
+        let data: Vec<Vec<Option<usize>>> = contents.iter()
+            .map(|resp| scale.keyed_answers(resp))
+            .filter(|row| row.iter().any(Option::is_some))
+            .collect();
+        let ids: Vec<String> = scale.items.iter().map(|item| item.id.clone()).collect();
+        let items = fit_grm(&ids, scale.categories, &data);
+        println!("{} ({} responses):", scale.name, data.len());
+        for item in &items {
+            println!("  {}: a = {:.3}, b = {:.3?}, I(0) = {:.3}",
+                     item.id, item.discrimination, item.thresholds, item.information(0.0));
+        }
+        calibration.scales.insert(scale.id.clone(), items);
+    }
+    calibration.save(&test.id);
+}
+
+// Parses the export filter options; `None` if any of them is not understood.
+fn export_filter(options: &[&str]) -> Option<ExportFilter> {
+    let mut filter = ExportFilter::default();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match *option {
+            "--from" => filter.from = Some(options.next()?.to_string()),
+            "--to" => filter.to = Some(options.next()?.to_string()),
+            "--completed" => filter.completed = true,
+            "--consented" => filter.consented = true,
+            "--exclude-flagged" => filter.exclude_flagged = true,
+            "--text" => filter.text = true,
+            _ => return None,
+        }
+    }
+    Some(filter.checked())
+}
+
+pub async fn export_parquet(test: &Test, path: &str, filter: ExportFilter) {
+    let pool = database::connect().await;
+    let mut conn = pool.acquire().await.unwrap();
+    let dataset = Dataset::new(test, filter.text);
+    let records = database::stream_records(test, &filter, &mut conn);
+    let file = BufWriter::new(File::create(path).unwrap());
+    write_parquet(&dataset, &filter, records, file).await.into_inner().unwrap();
+}
+
+pub async fn release(test: &Test, k: usize) {
+    let pool = database::connect().await;
+    let release = release::build(test, k, &mut pool.acquire().await.unwrap()).await;
+    println!("Wrote release v{} of {} to {}: {} responses, demographics cleared for {}, {} left out",
+             release.version, test.id, release.dir, release.responses, release.suppressed, release.dropped);
+}
+
+pub async fn retention(dry_run: bool) {
+    let policy = Policy::load(&rocket::Config::figment()).expect("No retention policy in Rocket.toml");
+    let pool = database::connect().await;
+    println!("{}", retention::apply(&policy, dry_run, &mut pool.acquire().await.unwrap()).await);
+}
+
+pub async fn run(args: &[String]) {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["calibrate", test] => calibrate(get_test(test), false).await,
+        ["calibrate", test, "--exclude-flagged"] => calibrate(get_test(test), true).await,
+        ["assess-quality", test] => {
+            let pool = database::connect().await;
+            quality::assess_and_store(get_test(test), &mut pool.acquire().await.unwrap()).await;
+        }
+        ["export-parquet", test, path, options @ ..] => match export_filter(options) {
+            Some(filter) => export_parquet(get_test(test), path, filter).await,
+            None => usage(),
+        },
+        ["codebook", test, "md"] => print!("{}", Codebook::new(get_test(test)).markdown()),
+        ["codebook", test, "xml"] => print!("{}", Codebook::new(get_test(test)).ddi()),
+        ["release", test] => release(get_test(test), release::DEFAULT_K).await,
+        ["release", test, k] => match k.parse() {
+            Ok(k) => release(get_test(test), k).await,
+            Err(_) => usage(),
+        },
+        ["access-link", id] => match id.parse::<Uuid>() {
+            Ok(id) => println!("/access/{}/{}", id, access::token(id)),
+            Err(_) => usage(),
+        },
+        ["retention"] => retention(false).await,
+        ["retention", "--dry-run"] => retention(true).await,
+        _ => usage(),
+    }
+}
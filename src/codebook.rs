@@ -0,0 +1,243 @@
+use rocket::serde::{Serialize, Serializer};
+use serde_json::json;
+use crate::dataset::{Column, Dataset, Kind, Source};
+use crate::tests::{Condition, QuestionContent, Test};
+
+// Documentation of every variable in a test's exported dataset, generated from the test
+// definition so that it always matches the exports. It is rendered as Markdown, as HTML through
+// the admin templates, and as DDI-Codebook 2.5 XML for data archives.
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Code {
+    pub value: i64,
+    pub label: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Variable {
+    pub name: String,
+    #[serde(serialize_with = "serialize_kind")]
+    pub kind: Kind,
+    pub label: String,
+    /// 1-based number of the page the question is on.
+    pub page: Option<usize>,
+    pub universe: String,
+    pub codes: Vec<Code>,
+    pub keying: Vec<String>,
+    pub derivation: Option<String>,
+    pub privacy: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Codebook {
+    pub test_id: String,
+    pub title: String,
+    pub variables: Vec<Variable>,
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Integer => "integer",
+        Kind::Float => "decimal",
+        Kind::Boolean => "boolean (0/1)",
+        Kind::Text => "text",
+        Kind::Timestamp => "timestamp",
+    }
+}
+
+fn serialize_kind<S: Serializer>(kind: &Kind, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(kind_name(*kind))
+}
+
+fn page_of(test: &Test, question: &str) -> Option<usize> {
+    test.pages.iter().position(|page| page.elements.iter().any(|q| q.id == question))
+}
+
+fn universe(test: &Test, column: &Column) -> String {
+    let page_index = match column.question().and_then(|id| page_of(test, id)) {
+        Some(index) => index,
+        None => return "All respondents".into(),
+    };
+    let page = &test.pages[page_index];
+    let mut universe = match &page.condition {
+        Condition::Always => "All respondents who reached page".to_string(),
+        Condition::Question { id, value } if *value == json!({"checked": true}) =>
+            format!("Respondents who checked {} and reached page", id),
+        Condition::Question { id, value } =>
+            format!("Respondents whose answer to {} matches {} and who reached page", id, value),
+    };
+    universe += &format!(" {}", page_index + 1);
+    if test.adaptive(page_index).is_some() {
+        universe += "; items are administered adaptively, so not every respondent sees every item";
+    }
+    if matches!(column.source, Source::Passed(_)) {
+        universe += "; shown at a random position among the page's items";
+    }
+    if matches!(column.source, Source::Other(_)) {
+        universe += ", if they chose \"Other\"";
+    }
+    universe
+}
+
+fn keying(test: &Test, column: &Column) -> Vec<String> {
+    let id = match &column.source {
+        Source::Ordinal(id) => id,
+        _ => return vec![],
+    };
+    test.scales.iter().flat_map(|scale| {
+        scale.items.iter().filter(|item| item.id == *id).map(move |item| {
+            if item.reversed {
+                format!("{}: reverse-keyed, scored as {} - code", scale.name, scale.categories - 1)
+            }
+            else {
+                format!("{}: keyed positively, scored as the code", scale.name)
+            }
+        })
+    }).collect()
+}
+
+fn derivation(test: &Test, column: &Column) -> Option<String> {
+    let keyed_items = |scale_id: &str| {
+        let scale = test.scale(scale_id).unwrap();
+        let items: Vec<String> = scale.items.iter().map(|item| {
+            if item.reversed { format!("({} - {})", scale.categories - 1, item.id) } else { item.id.clone() }
+        }).collect();
+        items.join(", ")
+    };
+    match &column.source {
+        Source::Nominal(_) => Some("Text of the chosen option".into()),
+        Source::Passed(id) => test.pages.iter().flat_map(|page| &page.elements)
+            .find(|q| q.id == *id)
+            .and_then(|q| match &q.content {
+                QuestionContent::McQuestion { options, expected: Some(expected) } =>
+                    Some(format!("1 if the instructed option {} ({}) was chosen, else 0", expected, options[*expected])),
+                _ => None,
+            }),
+        Source::ScaleMean(id) => Some(format!(
+            "1 + mean({}), over the answered items", keyed_items(id))),
+        Source::ScaleTheta(id) => Some(format!(
+            "Expected a posteriori estimate under the calibrated graded response model with a \
+             standard normal prior, from the keyed answers {}", keyed_items(id))),
+        Source::Flags => Some(
+            "Space-separated careless responding flags: longstring, irv, synonyms, antonyms, mahalanobis, \
+             speed, attention".into()),
+        _ => None,
+    }
+}
+
+fn privacy(test: &Test, column: &Column) -> String {
+    let question = column.question();
+    let publishable = match &test.consent {
+        Some(consent) => format!("Publishable only for respondents who checked {}.", consent),
+        None => "Not publishable; the test does not ask for consent to publication.".into(),
+    };
+    match &column.source {
+        Source::ResponseId => "Identifies the stored response; internal only.".into(),
+        Source::StartTime | Source::SubmitTime => "Exact time; internal only.".into(),
+        _ if question.is_some_and(|id| test.private.iter().any(|private| private == id)) =>
+            "Private: kept private even if the respondent consented to publication.".into(),
+        Source::Other(_) | Source::Answer(_) =>
+            format!("Free text that may identify the respondent; internal only. {}", publishable),
+        _ if question.is_some() && question == test.consent.as_deref() =>
+            "Records consent to publication of the response.".into(),
+        _ => publishable,
+    }
+}
+
+impl Codebook {
+    pub fn new(test: &Test) -> Codebook {
+        Codebook::from_columns(test, &Dataset::new(test, true).columns)
+    }
+
+    /// Documents a selection of a test's columns, such as those of a public release.
+    pub fn from_columns(test: &Test, columns: &[Column]) -> Codebook {
+        let variables = columns.iter().map(|column| Variable {
+            name: column.name.clone(),
+            kind: column.kind,
+            label: column.label.clone(),
+            page: column.question().and_then(|id| page_of(test, id)).map(|index| index + 1),
+            universe: universe(test, column),
+            codes: column.value_labels.iter().map(|(value, label)| Code { value: *value, label: label.clone() }).collect(),
+            keying: keying(test, column),
+            derivation: derivation(test, column),
+            privacy: privacy(test, column),
+        }).collect();
+        Codebook { test_id: test.id.clone(), title: test.name.clone(), variables }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut md = format!("# Codebook: {}\n\n", self.title);
+        md += "| Variable | Type | Label |\n|---|---|---|\n";
+        for var in &self.variables {
+            md += &format!("| `{}` | {} | {} |\n", var.name, kind_name(var.kind), var.label.replace('|', "\\|"));
+        }
+        for var in &self.variables {
+            md += &format!("\n## `{}`\n\n{}\n\n", var.name, var.label);
+            md += &format!("- Type: {}\n", kind_name(var.kind));
+            if let Some(page) = var.page {
+                md += &format!("- Page: {}\n", page);
+            }
+            md += &format!("- Universe: {}\n", var.universe);
+            for keying in &var.keying {
+                md += &format!("- Scale: {}\n", keying);
+            }
+            if let Some(derivation) = &var.derivation {
+                md += &format!("- Derivation: {}\n", derivation);
+            }
+            md += &format!("- Privacy: {}\n", var.privacy);
+            if !var.codes.is_empty() {
+                md += "\n| Code | Label |\n|---|---|\n";
+                for code in &var.codes {
+                    md += &format!("| {} | {} |\n", code.value, code.label.replace('|', "\\|"));
+                }
+            }
+        }
+        md
+    }
+
+    pub fn ddi(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml += "<codeBook xmlns=\"ddi:codebook:2_5\" version=\"2.5\">\n";
+        xml += &format!(
+            "  <stdyDscr>\n    <citation>\n      <titlStmt>\n        <titl>{}</titl>\n        <IDNo>{}</IDNo>\n      </titlStmt>\n    </citation>\n  </stdyDscr>\n",
+            escape(&self.title), escape(&self.test_id));
+        xml += "  <dataDscr>\n";
+        for (i, var) in self.variables.iter().enumerate() {
+            let interval = match var.kind {
+                Kind::Integer | Kind::Boolean => "discrete",
+                _ => "contin",
+            };
+            xml += &format!("    <var ID=\"V{}\" name=\"{}\" intrvl=\"{}\">\n", i + 1, escape(&var.name), interval);
+            xml += &format!("      <labl>{}</labl>\n", escape(&var.label));
+            xml += &format!("      <security>{}</security>\n", escape(&var.privacy));
+            if var.page.is_some() {
+                xml += &format!("      <qstn><qstnLit>{}</qstnLit></qstn>\n", escape(&var.label));
+            }
+            xml += &format!("      <universe>{}</universe>\n", escape(&var.universe));
+            for code in &var.codes {
+                xml += &format!("      <catgry><catValu>{}</catValu><labl>{}</labl></catgry>\n", code.value, escape(&code.label));
+            }
+            if !var.keying.is_empty() {
+                xml += &format!("      <codInstr>{}</codInstr>\n", escape(&var.keying.join("; ")));
+            }
+            if let Some(derivation) = &var.derivation {
+                xml += &format!("      <derivation><drvdesc>{}</drvdesc></derivation>\n", escape(derivation));
+            }
+            xml += &match var.kind {
+                Kind::Text => "      <varFormat type=\"character\"/>\n".to_string(),
+                Kind::Timestamp => "      <varFormat type=\"numeric\" category=\"date\"/>\n".to_string(),
+                _ => "      <varFormat type=\"numeric\"/>\n".to_string(),
+            };
+            xml += "    </var>\n";
+        }
+        xml += "  </dataDscr>\n</codeBook>\n";
+        xml
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,108 @@
+use rocket::serde::Serialize;
+use serde_json::Value;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::database;
+use crate::quality::pearson;
+use crate::signing;
+use crate::tests::{FeedbackItem, Test};
+
+// Compatibility reports compare the results of two respondents to the same test. Each finds a
+// compatibility code on their feedback page; once both have entered the other's code, each can
+// see the two results side by side. Entering a code alone shows nothing, so a respondent's
+// results are only compared with someone they gave their code to and who gave theirs back.
+
+const CODE_PURPOSE: &str = "compatibility-code";
+
+/// Makes the response's compatibility code usable and returns it.
+pub async fn issue_code(response_id: Uuid, conn: &mut PoolConnection<Postgres>) -> String {
+    let code = signing::code(CODE_PURPOSE, &response_id.to_string());
+    database::set_compatibility_hash(response_id, &signing::code_hash(&code), conn).await;
+    code
+}
+
+/// Records that the respondent entered another respondent's code; `false` if no other completed
+/// response to the test has it.
+pub async fn link(test: &Test, response_id: Uuid, code: &str, conn: &mut PoolConnection<Postgres>) -> bool {
+    match database::find_by_compatibility_hash(&test.id, &signing::code_hash(code), conn).await {
+        Some(other_id) if other_id != response_id => {
+            database::insert_compatibility_link(response_id, other_id, conn).await;
+            true
+        }
+        _ => false,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Partner {
+    pub response_id: String,
+    pub mutual: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CompatibilitySection {
+    pub code: String,
+    pub partners: Vec<Partner>,
+    /// People who entered this respondent's code but whose code wasn't entered in return.
+    pub unreturned: i64,
+}
+
+pub async fn section(response_id: Uuid, conn: &mut PoolConnection<Postgres>) -> CompatibilitySection {
+    let partners = database::get_compatibility_links(response_id, conn).await.into_iter()
+        .map(|(other_id, mutual)| Partner { response_id: other_id.to_string(), mutual })
+        .collect();
+    CompatibilitySection {
+        code: issue_code(response_id, conn).await,
+        partners,
+        unreturned: database::count_unreturned_links(response_id, conn).await,
+    }
+}
+
+/// A feedback item scored for both respondents. For scale scores, `difference` is how far apart
+/// the two bars are, in percentage points.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ComparedItem {
+    pub own: FeedbackItem,
+    pub other: FeedbackItem,
+    pub difference: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Comparison {
+    pub items: Vec<ComparedItem>,
+    /// The correlation between the two profiles of scale scores.
+    pub similarity: Option<f64>,
+    /// The mean absolute difference over the scales, in percentage points.
+    pub mean_difference: Option<f64>,
+}
+
+fn percentage(item: &FeedbackItem) -> Option<f64> {
+    match item {
+        FeedbackItem::Bar { score, min, max } => Some(100.0 * (score - min) / (max - min)),
+        _ => None,
+    }
+}
+
+pub fn compare(test: &Test, own: &Value, other: &Value) -> Comparison {
+    let items: Vec<ComparedItem> = test.feedback.iter().filter_map(|part| {
+        let (own, other) = (part.score(test, own)?, part.score(test, other)?);
+        let difference = percentage(&own).zip(percentage(&other)).map(|(a, b)| (a - b).abs());
+        Some(ComparedItem { own, other, difference })
+    }).collect();
+    let (owns, others): (Vec<f64>, Vec<f64>) = items.iter()
+        .filter_map(|item| percentage(&item.own).zip(percentage(&item.other)))
+        .unzip();
+    let differences: Vec<f64> = items.iter().filter_map(|item| item.difference).collect();
+    Comparison {
+        similarity: if owns.len() >= 3 { pearson(&owns, &others) } else { None },
+        mean_difference: if differences.is_empty() { None } else {
+            Some(differences.iter().sum::<f64>() / differences.len() as f64)
+        },
+        items,
+    }
+}
@@ -0,0 +1,257 @@
+use rocket::serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::irt::eap;
+use crate::quality::Quality;
+use crate::tests::{QuestionContent, Test};
+use crate::util::{format_timestamp, is_iso_date};
+
+// Flattens stored responses into a wide table with one row per respondent and one column per
+// variable, laid out from the test definition. Exporters turn this table into files.
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "rocket::serde")]
+pub enum Kind {
+    Integer,
+    Float,
+    Boolean,
+    Text,
+    Timestamp,
+}
+
+/// Where in a response a column's values are read from.
+#[derive(Debug)]
+pub enum Source {
+    ResponseId,
+    StartTime,
+    SubmitTime,
+    Completed,
+    Ordinal(String),
+    // The chosen option of a vertical question, where "Other" is coded after the listed options.
+    Choice(String, usize),
+    Nominal(String),
+    Other(String),
+    Checked(String),
+    Passed(String),
+    Answer(String),
+    ScaleMean(String),
+    ScaleTheta(String),
+    Flags,
+}
+
+#[derive(Debug)]
+pub struct Column {
+    pub name: String,
+    pub kind: Kind,
+    pub label: String,
+    pub value_labels: Vec<(i64, String)>,
+    pub source: Source,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Missing,
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Text(String),
+    // Seconds since the Unix epoch.
+    Timestamp(f64),
+}
+
+/// A stored response as read for export.
+pub struct Record {
+    pub response_id: Uuid,
+    pub start_time: f64,
+    pub submit_time: f64,
+    pub completed: bool,
+    pub content: Value,
+    pub quality: Option<Value>,
+}
+
+#[derive(FromForm, Default)]
+pub struct ExportFilter {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub completed: bool,
+    pub consented: bool,
+    pub exclude_flagged: bool,
+    pub text: bool,
+}
+
+pub struct Dataset<'a> {
+    pub test: &'a Test,
+    pub columns: Vec<Column>,
+}
+
+impl ExportFilter {
+    /// Drops dates that are not in YYYY-MM-DD form, which the database could not compare.
+    pub fn checked(self) -> ExportFilter {
+        ExportFilter {
+            from: self.from.filter(|d| is_iso_date(d)),
+            to: self.to.filter(|d| is_iso_date(d)),
+            ..self
+        }
+    }
+}
+
+impl Column {
+    /// The id of the question the column is read from, if any.
+    pub fn question(&self) -> Option<&str> {
+        match &self.source {
+            Source::Ordinal(id) | Source::Choice(id, _) | Source::Nominal(id) | Source::Other(id)
+            | Source::Checked(id) | Source::Passed(id) | Source::Answer(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    fn new(name: String, kind: Kind, source: Source) -> Column {
+        Column { name, kind, label: String::new(), value_labels: vec![], source }
+    }
+
+    fn label(self, label: &str) -> Column {
+        Column { label: label.into(), ..self }
+    }
+
+    fn values(self, value_labels: Vec<(i64, String)>) -> Column {
+        Column { value_labels, ..self }
+    }
+}
+
+impl Cell {
+    pub fn to_text(&self) -> String {
+        match self {
+            Cell::Missing => "".into(),
+            Cell::Integer(n) => n.to_string(),
+            Cell::Float(x) => x.to_string(),
+            Cell::Boolean(b) => (if *b { "1" } else { "0" }).into(),
+            Cell::Text(s) => s.clone(),
+            Cell::Timestamp(t) => format_timestamp(*t),
+        }
+    }
+}
+
+impl<'a> Dataset<'a> {
+    /// Lays out the columns for a test. Free-text answers are only included if `text` is set.
+    /// Variable labels come from the text shown just before a question (its `AlignText` or the
+    /// closest header), and value labels from the options of multiple choice questions.
+    pub fn new(test: &'a Test, text: bool) -> Dataset<'a> {
+        use Kind::*;
+        let no_yes = || vec![(0, "No".to_string()), (1, "Yes".to_string())];
+        let mut columns = vec![
+            Column::new("response_id".into(), Text, Source::ResponseId).label("Response id"),
+            Column::new("start_time".into(), Timestamp, Source::StartTime).label("Time the test was started"),
+            Column::new("submit_time".into(), Timestamp, Source::SubmitTime).label("Time of the last submitted page"),
+            Column::new("completed".into(), Boolean, Source::Completed).label("Completed the test").values(no_yes()),
+        ];
+        for (q, label) in test.questions() {
+            let id = &q.id;
+            match &q.content {
+                QuestionContent::McQuestion { expected: Some(_), .. } => {
+                    columns.push(Column::new(format!("{}_passed", id), Boolean, Source::Passed(id.clone()))
+                        .label(&format!("Attention check passed: {}", label)).values(no_yes()));
+                }
+                QuestionContent::McQuestion { options, .. } => {
+                    let codes = options.iter().cloned().enumerate().map(|(i, o)| (i as i64, o)).collect();
+                    columns.push(Column::new(id.clone(), Integer, Source::Ordinal(id.clone())).label(label).values(codes));
+                    columns.push(Column::new(format!("{}_nom", id), Text, Source::Nominal(id.clone())).label(label));
+                }
+                QuestionContent::McQuestionVert { options, other } => {
+                    let mut codes: Vec<(i64, String)> = options.iter().cloned().enumerate().map(|(i, o)| (i as i64, o)).collect();
+                    if *other {
+                        codes.push((options.len() as i64, "Other".into()));
+                    }
+                    columns.push(Column::new(id.clone(), Integer, Source::Choice(id.clone(), options.len())).label(label).values(codes));
+                    columns.push(Column::new(format!("{}_nom", id), Text, Source::Nominal(id.clone())).label(label));
+                    if *other && text {
+                        columns.push(Column::new(format!("{}_other", id), Text, Source::Other(id.clone()))
+                            .label(&format!("{} (other, specified)", label)));
+                    }
+                }
+                QuestionContent::CheckboxQuestion { text: statement } => {
+                    columns.push(Column::new(id.clone(), Boolean, Source::Checked(id.clone())).label(statement).values(no_yes()));
+                }
+                QuestionContent::TextAreaQuestion => {
+                    if text {
+                        columns.push(Column::new(id.clone(), Text, Source::Answer(id.clone())).label(label));
+                    }
+                }
+                QuestionContent::Header { .. } | QuestionContent::Paragraph { .. } | QuestionContent::AlignText { .. } => {}
+            }
+        }
+        for scale in &test.scales {
+            columns.push(Column::new(format!("{}_mean", scale.id), Float, Source::ScaleMean(scale.id.clone()))
+                .label(&format!("{} (mean item score)", scale.name)));
+            if test.calibration.as_ref().is_some_and(|cal| cal.scales.contains_key(&scale.id)) {
+                columns.push(Column::new(format!("{}_theta", scale.id), Float, Source::ScaleTheta(scale.id.clone()))
+                    .label(&format!("{} (EAP trait estimate)", scale.name)));
+            }
+        }
+        columns.push(Column::new("quality_flags".into(), Text, Source::Flags).label("Careless responding flags"));
+        Dataset { test, columns }
+    }
+
+    pub fn row(&self, record: &Record) -> Vec<Cell> {
+        let content = &record.content;
+        let text = |v: &Value| v.as_str().map_or(Cell::Missing, |s| Cell::Text(s.into()));
+        let boolean = |v: &Value| v.as_bool().map_or(Cell::Missing, Cell::Boolean);
+        self.columns.iter().map(|column| match &column.source {
+            Source::ResponseId => Cell::Text(record.response_id.to_string()),
+            Source::StartTime => Cell::Timestamp(record.start_time),
+            Source::SubmitTime => Cell::Timestamp(record.submit_time),
+            Source::Completed => Cell::Boolean(record.completed),
+            Source::Ordinal(id) => content[id]["ord"].as_i64().map_or(Cell::Missing, Cell::Integer),
+            Source::Choice(id, n_options) => match content[id]["ord"].as_i64() {
+                Some(ord) => Cell::Integer(ord),
+                None if content[id]["nom"] == "Other" => Cell::Integer(*n_options as i64),
+                None => Cell::Missing,
+            },
+            Source::Nominal(id) => text(&content[id]["nom"]),
+            Source::Other(id) => text(&content[id]["answer"]),
+            Source::Checked(id) => boolean(&content[id]["checked"]),
+            Source::Passed(id) => boolean(&content[id]["passed"]),
+            Source::Answer(id) => text(&content[id]["answer"]),
+            Source::ScaleMean(id) => self.test.scale(id).unwrap().mean(content).map_or(Cell::Missing, Cell::Float),
+            Source::ScaleTheta(id) => {
+                let scale = self.test.scale(id).unwrap();
+                let answers = scale.keyed_answers(content);
+                if answers.iter().all(Option::is_none) {
+                    Cell::Missing
+                }
+                else {
+                    let items = &self.test.calibration.as_ref().unwrap().scales[id];
+                    Cell::Float(eap(items, &answers).theta)
+                }
+            }
+            Source::Flags => match &record.quality {
+                Some(quality) => Cell::Text(quality["flags"].as_array().unwrap().iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ")),
+                None => Cell::Missing,
+            },
+        }).collect()
+    }
+
+    /// Whether a record passes the filter's conditions that are not applied in the database.
+    pub fn keep(filter: &ExportFilter, record: &Record) -> bool {
+        !(filter.exclude_flagged && record.quality.as_ref().is_some_and(Quality::flagged))
+    }
+}
+
+/// Delimiter-separated text, quoted as in RFC 4180 when the delimiter is a comma. Tab-separated
+/// output has no quoting, so tabs and line breaks inside values become spaces.
+pub fn delimited_line(cells: &[String], delimiter: char) -> String {
+    let fields: Vec<String> = cells.iter().map(|cell| {
+        if delimiter == '\t' {
+            cell.replace(['\t', '\n', '\r'], " ")
+        }
+        else if cell.contains([delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        }
+        else {
+            cell.clone()
+        }
+    }).collect();
+    fields.join(&delimiter.to_string()) + "\n"
+}
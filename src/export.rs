@@ -0,0 +1,14 @@
+// Writers for statistics package and columnar formats, built from the flattened `Dataset` table.
+
+pub mod sav;
+pub mod dta;
+pub mod parquet;
+
+// Cuts a string to at most `max` bytes without splitting a character.
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
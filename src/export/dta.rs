@@ -0,0 +1,246 @@
+use crate::dataset::{Cell, Column, Kind};
+use time::macros::format_description;
+use crate::util::{date_time, now};
+use super::truncate;
+
+// Stata 14+ datasets (.dta format 118), little-endian. Integer columns are stored as longs and
+// booleans as bytes, each with a value label set named after the variable; timestamps are %tc
+// doubles counting milliseconds from 1960-01-01.
+
+const TYPE_DOUBLE: u16 = 65526;
+const TYPE_LONG: u16 = 65528;
+const TYPE_BYTE: u16 = 65530;
+const MAX_STR: usize = 2045;
+
+const MISSING_DOUBLE: u64 = 0x7fe0000000000000;
+const MISSING_LONG: i32 = 2147483621;
+const MISSING_BYTE: i8 = 101;
+// Seconds from the Stata epoch, 1960-01-01, to the Unix epoch.
+const STATA_EPOCH_OFFSET: f64 = 315619200.0;
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn tag(&mut self, tag: &str) {
+        self.0.extend_from_slice(tag.as_bytes());
+    }
+
+    // Writes `s` null-padded (or cut) to exactly `len` bytes.
+    fn padded(&mut self, s: &str, len: usize) {
+        let s = truncate(s, len);
+        self.0.extend_from_slice(s.as_bytes());
+        self.0.extend(std::iter::repeat_n(0, len - s.len()));
+    }
+
+    fn u16(&mut self, n: u16) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn i32(&mut self, n: i32) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+fn value_label_table(labels: &[(i64, String)]) -> Vec<u8> {
+    let mut offsets = vec![];
+    let mut text = vec![];
+    for (_, label) in labels {
+        offsets.push(text.len() as i32);
+        text.extend_from_slice(truncate(label, 32000).as_bytes());
+        text.push(0);
+    }
+    let mut table = vec![];
+    table.extend_from_slice(&(labels.len() as i32).to_le_bytes());
+    table.extend_from_slice(&(text.len() as i32).to_le_bytes());
+    for offset in offsets {
+        table.extend_from_slice(&offset.to_le_bytes());
+    }
+    for (value, _) in labels {
+        table.extend_from_slice(&(*value as i32).to_le_bytes());
+    }
+    table.extend(text);
+    table
+}
+
+pub fn write_dta(data_label: &str, columns: &[Column], rows: &[Vec<Cell>]) -> Vec<u8> {
+    let types: Vec<u16> = columns.iter().enumerate().map(|(i, column)| match column.kind {
+        Kind::Integer => TYPE_LONG,
+        Kind::Boolean => TYPE_BYTE,
+        Kind::Float | Kind::Timestamp => TYPE_DOUBLE,
+        Kind::Text => rows.iter()
+            .map(|row| if let Cell::Text(s) = &row[i] { truncate(s, MAX_STR).len() } else { 0 })
+            .max().unwrap_or(0).max(1) as u16,
+    }).collect();
+    let created = date_time(now());
+    let mut map = [0u64; 14];
+
+    let mut w = Writer(vec![]);
+    w.tag("<stata_dta><header><release>118</release><byteorder>LSF</byteorder><K>");
+    w.u16(columns.len() as u16);
+    w.tag("</K><N>");
+    w.u64(rows.len() as u64);
+    w.tag("</N><label>");
+    let label = truncate(data_label, 80);
+    w.u16(label.len() as u16);
+    w.tag(label);
+    w.tag("</label><timestamp>");
+    let timestamp = created.format(format_description!("[day] [month repr:short] [year] [hour]:[minute]")).unwrap();
+    w.0.push(timestamp.len() as u8);
+    w.tag(&timestamp);
+    w.tag("</timestamp></header>");
+
+    map[1] = w.0.len() as u64;
+    w.tag("<map>");
+    let map_pos = w.0.len();
+    w.0.extend(std::iter::repeat_n(0, 14 * 8));
+    w.tag("</map>");
+
+    map[2] = w.0.len() as u64;
+    w.tag("<variable_types>");
+    for t in &types {
+        w.u16(*t);
+    }
+    w.tag("</variable_types>");
+
+    map[3] = w.0.len() as u64;
+    w.tag("<varnames>");
+    for column in columns {
+        w.padded(truncate(&column.name, 32), 129);
+    }
+    w.tag("</varnames>");
+
+    map[4] = w.0.len() as u64;
+    w.tag("<sortlist>");
+    for _ in 0..=columns.len() {
+        w.u16(0);
+    }
+    w.tag("</sortlist>");
+
+    map[5] = w.0.len() as u64;
+    w.tag("<formats>");
+    for (column, t) in columns.iter().zip(&types) {
+        let format = match column.kind {
+            Kind::Integer => "%12.0g".to_string(),
+            Kind::Boolean => "%8.0g".to_string(),
+            Kind::Float => "%10.0g".to_string(),
+            Kind::Timestamp => "%tc".to_string(),
+            Kind::Text => format!("%-{}s", t),
+        };
+        w.padded(&format, 57);
+    }
+    w.tag("</formats>");
+
+    map[6] = w.0.len() as u64;
+    w.tag("<value_label_names>");
+    for column in columns {
+        let name = if column.value_labels.is_empty() { "" } else { truncate(&column.name, 32) };
+        w.padded(name, 129);
+    }
+    w.tag("</value_label_names>");
+
+    map[7] = w.0.len() as u64;
+    w.tag("<variable_labels>");
+    for column in columns {
+        let mut label = &column.label[..];
+        if label.chars().count() > 80 {
+            label = &label[..label.char_indices().nth(80).unwrap().0];
+        }
+        w.padded(label, 321);
+    }
+    w.tag("</variable_labels>");
+
+    map[8] = w.0.len() as u64;
+    w.tag("<characteristics></characteristics>");
+
+    map[9] = w.0.len() as u64;
+    w.tag("<data>");
+    for row in rows {
+        for (cell, t) in row.iter().zip(&types) {
+            match (*t, cell) {
+                (TYPE_LONG, Cell::Integer(n)) => w.i32(*n as i32),
+                (TYPE_LONG, _) => w.i32(MISSING_LONG),
+                (TYPE_BYTE, Cell::Boolean(b)) => w.0.push(*b as u8),
+                (TYPE_BYTE, _) => w.0.push(MISSING_BYTE as u8),
+                (TYPE_DOUBLE, Cell::Float(x)) => w.0.extend_from_slice(&x.to_le_bytes()),
+                (TYPE_DOUBLE, Cell::Timestamp(t)) => {
+                    let ms = ((t + STATA_EPOCH_OFFSET) * 1000.0).round();
+                    w.0.extend_from_slice(&ms.to_le_bytes())
+                }
+                (TYPE_DOUBLE, _) => w.u64(MISSING_DOUBLE),
+                (width, Cell::Text(s)) => w.padded(s, width as usize),
+                (width, _) => w.padded("", width as usize),
+            }
+        }
+    }
+    w.tag("</data>");
+
+    map[10] = w.0.len() as u64;
+    w.tag("<strls></strls>");
+
+    map[11] = w.0.len() as u64;
+    w.tag("<value_labels>");
+    for column in columns.iter().filter(|c| !c.value_labels.is_empty()) {
+        let table = value_label_table(&column.value_labels);
+        w.tag("<lbl>");
+        w.i32(table.len() as i32);
+        w.padded(truncate(&column.name, 32), 129);
+        w.padded("", 3);
+        w.0.extend(table);
+        w.tag("</lbl>");
+    }
+    w.tag("</value_labels>");
+
+    map[12] = w.0.len() as u64;
+    w.tag("</stata_dta>");
+    map[13] = w.0.len() as u64;
+
+    for (i, offset) in map.iter().enumerate() {
+        w.0[map_pos + i * 8..map_pos + i * 8 + 8].copy_from_slice(&offset.to_le_bytes());
+    }
+    w.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use crate::dataset::Source;
+    use super::*;
+
+    #[test]
+    fn one_column_header() {
+        let column = Column { name: "score".into(), kind: Kind::Integer, label: "Score".into(), value_labels: vec![], source: Source::Completed };
+        let bytes = write_dta("Test", &[column], &[vec![Cell::Integer(3)]]);
+        let header = b"<stata_dta><header><release>118</release><byteorder>LSF</byteorder><K>";
+        assert_eq!(&bytes[..header.len()], header);
+        let mut at = header.len();
+        assert_eq!(u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap()), 1);
+        at += 2;
+        assert_eq!(&bytes[at..at + 7], b"</K><N>");
+        at += 7;
+        assert_eq!(u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()), 1);
+        at += 8;
+        assert_eq!(&bytes[at..at + 11], b"</N><label>");
+        at += 11;
+        assert_eq!(u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap()), 4);
+        assert_eq!(&bytes[at + 2..at + 6], b"Test");
+        assert!(bytes.ends_with(b"</stata_dta>"));
+
+        // The map gives the offset of each section, which starts with its tag.
+        let map_at = bytes.windows(5).position(|window| window == b"<map>").unwrap();
+        let map: Vec<usize> = (0..14)
+            .map(|i| u64::from_le_bytes(bytes[map_at + 5 + i * 8..map_at + 13 + i * 8].try_into().unwrap()) as usize)
+            .collect();
+        assert_eq!((map[0], map[1], map[13]), (0, map_at, bytes.len()));
+        for (i, tag) in [(2, "<variable_types>"), (3, "<varnames>"), (9, "<data>"), (11, "<value_labels>"), (12, "</stata_dta>")] {
+            assert_eq!(&bytes[map[i]..map[i] + tag.len()], tag.as_bytes());
+        }
+        let types = map[2] + "<variable_types>".len();
+        assert_eq!(u16::from_le_bytes(bytes[types..types + 2].try_into().unwrap()), TYPE_LONG);
+        let data = map[9] + "<data>".len();
+        assert_eq!(i32::from_le_bytes(bytes[data..data + 4].try_into().unwrap()), 3);
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::Write;
+use std::sync::Arc;
+use parquet::basic::{Compression, LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use rocket::futures::{Stream, StreamExt};
+use serde_json::json;
+use crate::dataset::{Cell, Column, Dataset, ExportFilter, Kind, Record};
+
+// Apache Parquet files with one optional column per dataset variable. Rows are written in row
+// groups as they are read, so large exports are never held in memory as a whole. Variable and
+// value labels, which Parquet has no place for, go into the file's key-value metadata as JSON.
+
+const ROW_GROUP_SIZE: usize = 65536;
+
+fn field(column: &Column) -> Type {
+    let (physical, logical) = match column.kind {
+        Kind::Integer => (PhysicalType::INT64, None),
+        Kind::Float => (PhysicalType::DOUBLE, None),
+        Kind::Boolean => (PhysicalType::BOOLEAN, None),
+        Kind::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        // Response times are stored without a time zone, so they are not marked as UTC.
+        Kind::Timestamp => (PhysicalType::INT64, Some(LogicalType::Timestamp {
+            is_adjusted_to_u_t_c: false,
+            unit: TimeUnit::MICROS(Default::default()),
+        })),
+    };
+    Type::primitive_type_builder(&column.name, physical)
+        .with_repetition(Repetition::OPTIONAL)
+        .with_logical_type(logical)
+        .build()
+        .unwrap()
+}
+
+fn labels(columns: &[Column]) -> String {
+    let labels: Vec<_> = columns.iter().map(|column| json!({
+        "name": column.name,
+        "label": column.label,
+        "values": column.value_labels.iter().map(|(value, label)| json!({"value": value, "label": label})).collect::<Vec<_>>(),
+    })).collect();
+    serde_json::to_string(&labels).unwrap()
+}
+
+pub struct ParquetWriter<W: Write + Send> {
+    writer: SerializedFileWriter<W>,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(file_label: &str, columns: &[Column], sink: W) -> ParquetWriter<W> {
+        let schema = Type::group_type_builder("schema")
+            .with_fields(columns.iter().map(|column| Arc::new(field(column))).collect())
+            .build()
+            .unwrap();
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_key_value_metadata(Some(vec![
+                KeyValue::new("survey-data.label".into(), file_label.to_string()),
+                KeyValue::new("survey-data.columns".into(), labels(columns)),
+            ]))
+            .build();
+        ParquetWriter { writer: SerializedFileWriter::new(sink, Arc::new(schema), Arc::new(properties)).unwrap() }
+    }
+
+    /// Writes the rows as one row group.
+    pub fn write_rows(&mut self, rows: &[Vec<Cell>]) {
+        let mut group = self.writer.next_row_group().unwrap();
+        let mut i = 0;
+        while let Some(mut column) = group.next_column().unwrap() {
+            let cells: Vec<&Cell> = rows.iter().map(|row| &row[i]).collect();
+            // Definition level 1 marks a present value and 0 a missing one; only present values
+            // are passed to the writer.
+            let levels: Vec<i16> = cells.iter().map(|cell| if **cell == Cell::Missing { 0 } else { 1 }).collect();
+            match column.untyped() {
+                ColumnWriter::Int64ColumnWriter(w) => {
+                    let values: Vec<i64> = cells.iter().filter_map(|cell| match cell {
+                        Cell::Integer(n) => Some(*n),
+                        Cell::Timestamp(t) => Some((t * 1e6).round() as i64),
+                        _ => None,
+                    }).collect();
+                    w.write_batch(&values, Some(&levels), None).unwrap();
+                }
+                ColumnWriter::DoubleColumnWriter(w) => {
+                    let values: Vec<f64> = cells.iter().filter_map(|cell| match cell {
+                        Cell::Float(x) => Some(*x),
+                        _ => None,
+                    }).collect();
+                    w.write_batch(&values, Some(&levels), None).unwrap();
+                }
+                ColumnWriter::BoolColumnWriter(w) => {
+                    let values: Vec<bool> = cells.iter().filter_map(|cell| match cell {
+                        Cell::Boolean(b) => Some(*b),
+                        _ => None,
+                    }).collect();
+                    w.write_batch(&values, Some(&levels), None).unwrap();
+                }
+                ColumnWriter::ByteArrayColumnWriter(w) => {
+                    let values: Vec<ByteArray> = cells.iter().filter_map(|cell| match cell {
+                        Cell::Text(s) => Some(ByteArray::from(s.as_str())),
+                        _ => None,
+                    }).collect();
+                    w.write_batch(&values, Some(&levels), None).unwrap();
+                }
+                _ => unreachable!(),
+            }
+            column.close().unwrap();
+            i += 1;
+        }
+        group.close().unwrap();
+    }
+
+    /// Writes the footer and returns the sink.
+    pub fn finish(self) -> W {
+        self.writer.into_inner().unwrap()
+    }
+}
+
+/// Writes the records that pass the filter to `sink`, a row group at a time.
+pub async fn write_parquet<W: Write + Send>(dataset: &Dataset<'_>, filter: &ExportFilter, records: impl Stream<Item = Record>, sink: W) -> W {
+    let mut writer = ParquetWriter::new(&dataset.test.name, &dataset.columns, sink);
+    let mut rows = Vec::with_capacity(ROW_GROUP_SIZE);
+    let mut records = Box::pin(records);
+    while let Some(record) = records.next().await {
+        if Dataset::keep(filter, &record) {
+            rows.push(dataset.row(&record));
+        }
+        if rows.len() == ROW_GROUP_SIZE {
+            writer.write_rows(&rows);
+            rows.clear();
+        }
+    }
+    if !rows.is_empty() {
+        writer.write_rows(&rows);
+    }
+    writer.finish()
+}
@@ -0,0 +1,201 @@
+use crate::dataset::{Cell, Column, Kind};
+use time::macros::format_description;
+use crate::util::{date_time, now};
+use super::truncate;
+
+// SPSS system files (.sav), uncompressed and little-endian. Variables get short names V1, V2, ...
+// in the dictionary and their real names through the long variable names record. Strings are
+// UTF-8, as declared in the character encoding record.
+
+const SYSMIS: f64 = -f64::MAX;
+// SPSS dates count seconds from the start of the Gregorian calendar, 1582-10-14.
+const SPSS_EPOCH_OFFSET: f64 = 12219379200.0;
+const MAX_STRING: usize = 255;
+const MAX_VALUE_LABEL: usize = 120;
+
+const FORMAT_A: i32 = 1;
+const FORMAT_F: i32 = 5;
+const FORMAT_DATETIME: i32 = 22;
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn int(&mut self, n: i32) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn float(&mut self, x: f64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    // Writes `s` space-padded (or cut) to exactly `len` bytes.
+    fn padded(&mut self, s: &str, len: usize) {
+        let s = truncate(s, len);
+        self.0.extend_from_slice(s.as_bytes());
+        self.0.extend(std::iter::repeat_n(b' ', len - s.len()));
+    }
+
+    fn extension(&mut self, subtype: i32, size: i32, data: &[u8]) {
+        self.int(7);
+        self.int(subtype);
+        self.int(size);
+        self.int(data.len() as i32 / size);
+        self.0.extend_from_slice(data);
+    }
+}
+
+fn format(kind: Kind, width: usize) -> i32 {
+    let (format, width, decimals) = match kind {
+        Kind::Integer | Kind::Boolean => (FORMAT_F, 8, 0),
+        Kind::Float => (FORMAT_F, 10, 4),
+        Kind::Timestamp => (FORMAT_DATETIME, 20, 0),
+        Kind::Text => (FORMAT_A, width as i32, 0),
+    };
+    (format << 16) | (width << 8) | decimals
+}
+
+fn numeric(cell: &Cell) -> f64 {
+    match cell {
+        Cell::Integer(n) => *n as f64,
+        Cell::Float(x) => *x,
+        Cell::Boolean(b) => if *b { 1.0 } else { 0.0 },
+        Cell::Timestamp(t) => t + SPSS_EPOCH_OFFSET,
+        Cell::Missing | Cell::Text(_) => SYSMIS,
+    }
+}
+
+pub fn write_sav(file_label: &str, columns: &[Column], rows: &[Vec<Cell>]) -> Vec<u8> {
+    // String variables are as wide as their longest value; numeric ones have width 0.
+    let widths: Vec<usize> = columns.iter().enumerate().map(|(i, column)| {
+        if column.kind != Kind::Text {
+            return 0;
+        }
+        rows.iter()
+            .map(|row| if let Cell::Text(s) = &row[i] { truncate(s, MAX_STRING).len() } else { 0 })
+            .max().unwrap_or(0).max(1)
+    }).collect();
+    let segments: Vec<usize> = widths.iter().map(|w| if *w == 0 { 1 } else { w.div_ceil(8) }).collect();
+    let created = date_time(now());
+
+    let mut w = Writer(vec![]);
+    w.0.extend_from_slice(b"$FL2");
+    w.padded("@(#) SPSS DATA FILE survey-data", 60);
+    w.int(2);
+    w.int(segments.iter().sum::<usize>() as i32);
+    w.int(0);
+    w.int(0);
+    w.int(rows.len() as i32);
+    w.float(100.0);
+    w.padded(&created.format(format_description!("[day] [month repr:short] [year repr:last_two]")).unwrap(), 9);
+    w.padded(&created.format(format_description!("[hour]:[minute]:[second]")).unwrap(), 8);
+    w.padded(file_label, 64);
+    w.padded("", 3);
+
+    for (i, column) in columns.iter().enumerate() {
+        let label = truncate(&column.label, MAX_STRING);
+        w.int(2);
+        w.int(widths[i] as i32);
+        w.int(if label.is_empty() { 0 } else { 1 });
+        w.int(0);
+        w.int(format(column.kind, widths[i]));
+        w.int(format(column.kind, widths[i]));
+        w.padded(&format!("V{}", i + 1), 8);
+        if !label.is_empty() {
+            w.int(label.len() as i32);
+            w.padded(label, label.len().div_ceil(4) * 4);
+        }
+        for _ in 1..segments[i] {
+            w.int(2);
+            w.int(-1);
+            w.int(0);
+            w.int(0);
+            w.int(0);
+            w.int(0);
+            w.padded("", 8);
+        }
+    }
+
+    let mut index = 1;
+    for (i, column) in columns.iter().enumerate() {
+        if !column.value_labels.is_empty() {
+            w.int(3);
+            w.int(column.value_labels.len() as i32);
+            for (value, label) in &column.value_labels {
+                let label = truncate(label, MAX_VALUE_LABEL);
+                w.float(*value as f64);
+                w.0.push(label.len() as u8);
+                w.padded(label, (label.len() + 1).div_ceil(8) * 8 - 1);
+            }
+            w.int(4);
+            w.int(1);
+            w.int(index as i32);
+        }
+        index += segments[i];
+    }
+
+    let mut info = vec![];
+    for n in [1, 0, 0, -1, 1, 1, 2, 65001] {
+        info.extend_from_slice(&i32::to_le_bytes(n));
+    }
+    w.extension(3, 4, &info);
+    let mut info = vec![];
+    for x in [SYSMIS, f64::MAX, f64::from_bits(0xffeffffffffffffe)] {
+        info.extend_from_slice(&x.to_le_bytes());
+    }
+    w.extension(4, 8, &info);
+    let long_names: Vec<String> = columns.iter().enumerate()
+        .map(|(i, column)| format!("V{}={}", i + 1, truncate(&column.name, 64)))
+        .collect();
+    w.extension(13, 1, long_names.join("\t").as_bytes());
+    w.extension(20, 1, b"UTF-8");
+    w.int(999);
+    w.int(0);
+
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            match (widths[i], cell) {
+                (0, cell) => w.float(numeric(cell)),
+                (_, Cell::Text(s)) => w.padded(s, segments[i] * 8),
+                (_, _) => w.padded("", segments[i] * 8),
+            }
+        }
+    }
+    w.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use crate::dataset::Source;
+    use super::*;
+
+    fn int(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn one_column_header() {
+        let column = Column { name: "score".into(), kind: Kind::Integer, label: "Score".into(), value_labels: vec![], source: Source::Completed };
+        let bytes = write_sav("Test", &[column], &[vec![Cell::Integer(3)]]);
+        assert_eq!(&bytes[..4], b"$FL2");
+        assert!(bytes[4..64].starts_with(b"@(#) SPSS DATA FILE"));
+        assert_eq!(int(&bytes, 64), 2);
+        assert_eq!(int(&bytes, 68), 1);
+        assert_eq!(int(&bytes, 72), 0);
+        assert_eq!(int(&bytes, 80), 1);
+        assert_eq!(f64::from_le_bytes(bytes[84..92].try_into().unwrap()), 100.0);
+        assert_eq!(&bytes[109..173], format!("{:64}", "Test").as_bytes());
+        // The variable record follows the 176-byte header.
+        assert_eq!(int(&bytes, 176), 2);
+        assert_eq!(int(&bytes, 180), 0);
+        assert_eq!(int(&bytes, 184), 1);
+        assert_eq!(int(&bytes, 192), (FORMAT_F << 16) | (8 << 8));
+        assert_eq!(&bytes[200..208], b"V1      ");
+        assert_eq!(int(&bytes, 208), 5);
+        assert_eq!(&bytes[212..220], b"Score   ");
+        // The dictionary ends with record 999, followed by the one value.
+        let end = bytes.len() - 8;
+        assert_eq!((int(&bytes, end - 8), int(&bytes, end - 4)), (999, 0));
+        assert_eq!(f64::from_le_bytes(bytes[end..].try_into().unwrap()), 3.0);
+    }
+}
@@ -0,0 +1,70 @@
+use rocket::serde::Serialize;
+use serde_json::Value;
+use crate::tests::{Condition, QuestionContent, Test};
+
+// Drop-off analysis. A response has reached a page if it got to that page or any later one,
+// whether or not the page itself was shown; a reached page that was never served was skipped
+// because its condition did not hold.
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FunnelPage {
+    pub page: usize,
+    pub title: String,
+    pub conditional: bool,
+    pub reached: usize,
+    pub skipped: usize,
+    pub continued: usize,
+    pub continuation_rate: f64,
+    pub skip_rate: f64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Funnel {
+    pub starts: usize,
+    pub completions: usize,
+    pub pages: Vec<FunnelPage>,
+}
+
+fn page_title(test: &Test, page: usize) -> String {
+    test.pages[page].elements.iter()
+        .find_map(|q| match &q.content {
+            QuestionContent::Header { title, .. } => Some(title.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| format!("Page {}", page + 1))
+}
+
+fn rate(part: usize, whole: usize) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 / whole as f64 }
+}
+
+/// Builds the funnel from each response's (last page reached, completed, paradata).
+pub fn funnel(test: &Test, progress: &[(usize, bool, Value)]) -> Funnel {
+    let n_pages = test.pages.len();
+    let reached = |page: usize| progress.iter()
+        .filter(|(last, completed, _)| *completed || *last >= page)
+        .count();
+    let completions = progress.iter().filter(|(_, completed, _)| *completed).count();
+    let pages = (0..n_pages).map(|page| {
+        let here = reached(page);
+        let continued = if page + 1 < n_pages { reached(page + 1) } else { completions };
+        let skipped = progress.iter()
+            .filter(|(last, completed, paradata)| {
+                (*completed || *last > page) && paradata["pages"][page.to_string()]["served"].is_null()
+            })
+            .count();
+        FunnelPage {
+            page,
+            title: page_title(test, page),
+            conditional: !matches!(test.pages[page].condition, Condition::Always),
+            reached: here,
+            skipped,
+            continued,
+            continuation_rate: rate(continued, here),
+            skip_rate: rate(skipped, here),
+        }
+    }).collect();
+    Funnel { starts: progress.len(), completions, pages }
+}
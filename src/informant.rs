@@ -0,0 +1,89 @@
+use rocket::serde::Serialize;
+use serde_json::Value;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::database;
+use crate::signing;
+use crate::tests::{get_test, Test};
+
+// Informant reports. A respondent to a test with an other-report variant can invite people who
+// know them through a signed link from their feedback. Each informant's response is stored under
+// the variant's test id with the target response in `target_id`. Once enough reports have come
+// in, the target's feedback shows their own scores next to the informants' average. The average
+// only takes in new reports in batches of `min_reports`, oldest first, so that comparing it before
+// and after a report comes in never reveals that single report's answers.
+
+const INVITE_PURPOSE: &str = "informant-invite";
+
+pub fn invite_signature(target_id: Uuid) -> String {
+    signing::sign(INVITE_PURPOSE, &target_id.to_string())
+}
+
+pub fn verify_invite(target_id: Uuid, signature: &str) -> bool {
+    signing::verify(INVITE_PURPOSE, &target_id.to_string(), signature)
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Comparison {
+    pub scale: String,
+    pub own: Option<f64>,
+    pub others: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct InformantFeedback {
+    pub signature: String,
+    /// The number of reports the average is based on, or the number so far until there are
+    /// enough.
+    pub reports: usize,
+    pub min_reports: usize,
+    /// Empty until there are at least `min_reports` reports.
+    pub comparison: Vec<Comparison>,
+}
+
+fn mean(xs: &[f64]) -> Option<f64> {
+    if xs.is_empty() { None } else { Some(xs.iter().sum::<f64>() / xs.len() as f64) }
+}
+
+/// The informant section of a response's feedback, if its test has an other-report variant.
+/// Informant reports are scored with the self-report test, so both sides are on the same scale.
+pub async fn feedback(test: &Test, response_id: Uuid, content: &Value, conn: &mut PoolConnection<Postgres>) -> Option<InformantFeedback> {
+    let informant = test.informant.as_ref()?;
+    let mut reports = database::get_informant_reports(response_id, conn).await;
+    if reports.len() >= informant.min_reports {
+        reports.truncate(reports.len() / informant.min_reports * informant.min_reports);
+    }
+    let comparison = if reports.len() < informant.min_reports { vec![] } else {
+        test.scales.iter().map(|scale| {
+            let others: Vec<f64> = reports.iter().filter_map(|report| test.scale_percentage(&scale.id, report)).collect();
+            Comparison {
+                scale: scale.name.clone(),
+                own: test.scale_percentage(&scale.id, content),
+                others: mean(&others),
+            }
+        }).collect()
+    };
+    Some(InformantFeedback {
+        signature: invite_signature(response_id),
+        reports: reports.len(),
+        min_reports: informant.min_reports,
+        comparison,
+    })
+}
+
+/// The other-report variant of the target response's test, if the response exists and its test
+/// has one.
+pub async fn variant(target_id: Uuid, conn: &mut PoolConnection<Postgres>) -> Option<&'static Test> {
+    let test_id = database::get_test_id(target_id, conn).await?;
+    get_test(&get_test(&test_id)?.informant.as_ref()?.test)
+}
+
+/// Starts an informant report on the target response and returns its id.
+pub async fn start(variant: &Test, target_id: Uuid, conn: &mut PoolConnection<Postgres>) -> Uuid {
+    let response_id = Uuid::new_v4();
+    database::create_informant_response(response_id, &variant.id, target_id, conn).await;
+    response_id
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fs;
+use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+// Samejima's graded response model. Each item has a discrimination `a` and ordered thresholds
+// b_1 < ... < b_{C-1}; the chance of answering in category c or above is logistic(a(theta - b_c)).
+
+const QUADRATURE_POINTS: usize = 41;
+const QUADRATURE_RANGE: f64 = 4.0;
+const EM_ITERATIONS: usize = 100;
+const M_STEP_ITERATIONS: usize = 25;
+const MIN_PROBABILITY: f64 = 1e-10;
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct GrmItem {
+    pub id: String,
+    pub discrimination: f64,
+    pub thresholds: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Calibration {
+    pub scales: HashMap<String, Vec<GrmItem>>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Estimate {
+    pub theta: f64,
+    pub se: f64,
+}
+
+impl Calibration {
+    fn path(test_id: &str) -> String {
+        format!("./calibrations/{}.json", test_id)
+    }
+
+    /// The test's saved calibration. A file that can't be read as one is reported and ignored, so
+    /// the test is scored as if it had never been calibrated.
+    pub fn load(test_id: &str) -> Option<Calibration> {
+        let path = Calibration::path(test_id);
+        let text = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&text) {
+            Ok(calibration) => Some(calibration),
+            Err(e) => {
+                error!("Ignoring the calibration in {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, test_id: &str) {
+        fs::create_dir_all("./calibrations").unwrap();
+        fs::write(Calibration::path(test_id), serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+}
+
+fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+impl GrmItem {
+    pub fn categories(&self) -> usize {
+        self.thresholds.len() + 1
+    }
+
+    // P(X >= c | theta) for c in 0..=C, so that category c has probability cum[c] - cum[c+1].
+    fn cumulative(&self, theta: f64) -> Vec<f64> {
+        let mut cum = vec![1.0];
+        for b in &self.thresholds {
+            cum.push(logistic(self.discrimination * (theta - b)));
+        }
+        cum.push(0.0);
+        cum
+    }
+
+    pub fn probabilities(&self, theta: f64) -> Vec<f64> {
+        let cum = self.cumulative(theta);
+        (0..self.categories()).map(|c| (cum[c] - cum[c + 1]).max(MIN_PROBABILITY)).collect()
+    }
+
+    pub fn information(&self, theta: f64) -> f64 {
+        let cum = self.cumulative(theta);
+        let deriv: Vec<f64> = cum.iter().map(|p| self.discrimination * p * (1.0 - p)).collect();
+        let probs = self.probabilities(theta);
+        (0..self.categories()).map(|c| (deriv[c] - deriv[c + 1]).powi(2) / probs[c]).sum()
+    }
+
+    // Expected complete-data log-likelihood, where r[q][c] is the expected number of respondents
+    // at quadrature point q that answered in category c.
+    fn log_likelihood(&self, nodes: &[(f64, f64)], r: &[Vec<f64>]) -> f64 {
+        let mut total = 0.0;
+        for (q, (theta, _)) in nodes.iter().enumerate() {
+            for (c, p) in self.probabilities(*theta).iter().enumerate() {
+                total += r[q][c] * p.ln();
+            }
+        }
+        total
+    }
+
+    fn gradient(&self, nodes: &[(f64, f64)], r: &[Vec<f64>]) -> (f64, Vec<f64>) {
+        let n_thr = self.thresholds.len();
+        let mut grad_a = 0.0;
+        let mut grad_b = vec![0.0; n_thr];
+        for (q, (theta, _)) in nodes.iter().enumerate() {
+            let cum = self.cumulative(*theta);
+            let probs = self.probabilities(*theta);
+            // Derivatives of cum[c] for c in 1..C; the fixed ends cum[0] and cum[C] contribute nothing.
+            let mut d_a = vec![0.0; cum.len()];
+            for c in 1..=n_thr {
+                d_a[c] = cum[c] * (1.0 - cum[c]) * (theta - self.thresholds[c - 1]);
+            }
+            for c in 0..self.categories() {
+                grad_a += r[q][c] / probs[c] * (d_a[c] - d_a[c + 1]);
+            }
+            for j in 1..=n_thr {
+                let d_b = -self.discrimination * cum[j] * (1.0 - cum[j]);
+                grad_b[j - 1] += (r[q][j] / probs[j] - r[q][j - 1] / probs[j - 1]) * d_b;
+            }
+        }
+        (grad_a, grad_b)
+    }
+
+    fn stepped(&self, step: f64, grad_a: f64, grad_b: &[f64]) -> GrmItem {
+        let mut thresholds: Vec<f64> = self.thresholds.iter().zip(grad_b)
+            .map(|(b, g)| (b + step * g).clamp(-QUADRATURE_RANGE * 2.0, QUADRATURE_RANGE * 2.0))
+            .collect();
+        for j in 1..thresholds.len() {
+            if thresholds[j] < thresholds[j - 1] + 1e-3 {
+                thresholds[j] = thresholds[j - 1] + 1e-3;
+            }
+        }
+        GrmItem {
+            id: self.id.clone(),
+            discrimination: (self.discrimination + step * grad_a).clamp(0.05, 6.0),
+            thresholds,
+        }
+    }
+
+    fn maximize(&mut self, nodes: &[(f64, f64)], r: &[Vec<f64>]) {
+        let mut current = self.log_likelihood(nodes, r);
+        let mut step = 0.1;
+        for _ in 0..M_STEP_ITERATIONS {
+            let (grad_a, grad_b) = self.gradient(nodes, r);
+            let total: f64 = r.iter().flatten().sum::<f64>().max(1.0);
+            let mut improved = false;
+            while step > 1e-8 {
+                let candidate = self.stepped(step / total, grad_a, &grad_b);
+                let value = candidate.log_likelihood(nodes, r);
+                if value > current {
+                    *self = candidate;
+                    current = value;
+                    step *= 2.0;
+                    improved = true;
+                    break;
+                }
+                step /= 2.0;
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+}
+
+fn quadrature() -> Vec<(f64, f64)> {
+    let mut nodes = vec![];
+    for i in 0..QUADRATURE_POINTS {
+        let theta = -QUADRATURE_RANGE + 2.0 * QUADRATURE_RANGE * i as f64 / (QUADRATURE_POINTS - 1) as f64;
+        nodes.push((theta, (-theta * theta / 2.0).exp()));
+    }
+    let total: f64 = nodes.iter().map(|(_, w)| w).sum();
+    nodes.into_iter().map(|(theta, w)| (theta, w / total)).collect()
+}
+
+fn posterior(items: &[GrmItem], answers: &[Option<usize>], nodes: &[(f64, f64)]) -> Vec<f64> {
+    let mut post: Vec<f64> = nodes.iter().map(|(theta, w)| {
+        let mut likelihood = *w;
+        for (item, answer) in items.iter().zip(answers) {
+            if let Some(c) = answer {
+                likelihood *= item.probabilities(*theta)[*c];
+            }
+        }
+        likelihood
+    }).collect();
+    let total: f64 = post.iter().sum();
+    for p in &mut post {
+        *p /= total;
+    }
+    post
+}
+
+/// Expected a posteriori trait estimate under a standard normal prior. Unanswered items are
+/// skipped, so this also works on a partially administered scale.
+pub fn eap(items: &[GrmItem], answers: &[Option<usize>]) -> Estimate {
+    let nodes = quadrature();
+    let post = posterior(items, answers, &nodes);
+    let theta: f64 = nodes.iter().zip(&post).map(|((t, _), p)| t * p).sum();
+    let var: f64 = nodes.iter().zip(&post).map(|((t, _), p)| (t - theta).powi(2) * p).sum();
+    Estimate { theta, se: var.sqrt() }
+}
+
+/// Fits a graded response model by marginal maximum likelihood (Bock-Aitkin EM). `data` has one
+/// row per respondent and one column per item, holding the keyed category or `None` if missing.
+pub fn fit_grm(ids: &[String], categories: usize, data: &[Vec<Option<usize>>]) -> Vec<GrmItem> {
+    let nodes = quadrature();
+    let mut items: Vec<GrmItem> = ids.iter().enumerate().map(|(i, id)| {
+        let answered: Vec<usize> = data.iter().filter_map(|row| row[i]).collect();
+        let n = answered.len().max(1) as f64;
+        let thresholds = (1..categories).map(|c| {
+            let p = (answered.iter().filter(|x| **x >= c).count() as f64 / n).clamp(0.01, 0.99);
+            ((1.0 - p) / p).ln()
+        }).collect();
+        GrmItem { id: id.clone(), discrimination: 1.0, thresholds }
+    }).collect();
+    for _ in 0..EM_ITERATIONS {
+        let mut r = vec![vec![vec![0.0; categories]; nodes.len()]; items.len()];
+        for row in data {
+            let post = posterior(&items, row, &nodes);
+            for (i, answer) in row.iter().enumerate() {
+                if let Some(c) = answer {
+                    for (q, p) in post.iter().enumerate() {
+                        r[i][q][*c] += p;
+                    }
+                }
+            }
+        }
+        for (item, r) in items.iter_mut().zip(&r) {
+            item.maximize(&nodes, r);
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers from a respondent at `theta`, taking the categories at evenly spaced quantiles of
+    // the model's distributions so that they follow it without a random number generator.
+    fn simulate(items: &[GrmItem], theta: f64) -> Vec<Option<usize>> {
+        items.iter().enumerate().map(|(i, item)| {
+            let u = (i as f64 + 0.5) / items.len() as f64;
+            let mut total = 0.0;
+            let probabilities = item.probabilities(theta);
+            Some(probabilities.iter().position(|p| { total += p; u < total }).unwrap_or(probabilities.len() - 1))
+        }).collect()
+    }
+
+    #[test]
+    fn eap_recovers_theta() {
+        let item = GrmItem { id: "item".into(), discrimination: 1.5, thresholds: vec![-1.5, -0.5, 0.5, 1.5] };
+        let items = vec![item; 100];
+        for theta in [-1.5, 0.0, 1.0] {
+            let estimate = eap(&items, &simulate(&items, theta));
+            assert!(estimate.se < 0.25, "{:?}", estimate);
+            assert!((estimate.theta - theta).abs() < 3.0 * estimate.se, "{:?} for {}", estimate, theta);
+        }
+    }
+
+    #[test]
+    fn eap_without_answers_is_the_prior() {
+        let items = vec![GrmItem { id: "item".into(), discrimination: 1.5, thresholds: vec![0.0] }];
+        let estimate = eap(&items, &[None]);
+        assert!(estimate.theta.abs() < 1e-6);
+        assert!((estimate.se - 1.0).abs() < 0.05);
+    }
+}
@@ -0,0 +1,189 @@
+use rocket::Route;
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use serde_json::{json, Map, Value};
+use crate::routes::api::{Answer, ErrorBody, FeedbackView, PageAnswers, PageView, ResponseInfo, TestInfo};
+use crate::tests::Test;
+
+// The OpenAPI 3 document of the JSON API. Nothing in it is written by hand that the code already
+// says: the schemas are derived from the types the API sends and receives, and the paths from the
+// routes mounted under the API's base when the server starts. A route that isn't described below
+// still appears with its method and parameters, so the document can't leave out part of the API,
+// and a test checks that every route is described.
+
+pub const API_BASE: &str = "/api/v1";
+
+/// The generated document, managed by the server.
+pub struct Document(pub Value);
+
+struct Operation {
+    summary: &'static str,
+    /// The route's path parameters, each with its schema type.
+    parameters: &'static [(&'static str, &'static str)],
+    request: Option<Schema>,
+    status: u16,
+    response: Schema,
+    errors: &'static [u16],
+}
+
+fn describe(name: &str, gen: &mut SchemaGenerator) -> Option<Operation> {
+    let operation = match name {
+        "tests" => Operation {
+            summary: "Lists the tests with their scales and estimated durations.",
+            parameters: &[],
+            request: None,
+            status: 200,
+            response: gen.subschema_for::<Vec<TestInfo>>(),
+            errors: &[],
+        },
+        "test" => Operation {
+            summary: "Gets the definition of a test: its pages, questions, feedback and scales.",
+            parameters: &[("id", "string")],
+            request: None,
+            status: 200,
+            response: gen.subschema_for::<Test>(),
+            errors: &[404],
+        },
+        "create_response" => Operation {
+            summary: "Starts a response to a test, returning its id, its token and the first page.",
+            parameters: &[("id", "string")],
+            request: None,
+            status: 201,
+            response: gen.subschema_for::<ResponseInfo>(),
+            errors: &[403, 404],
+        },
+        "response" => Operation {
+            summary: "Gets the state of a response and the page to continue from.",
+            parameters: &[("id", "string"), ("token", "string")],
+            request: None,
+            status: 200,
+            response: gen.subschema_for::<ResponseInfo>(),
+            errors: &[404],
+        },
+        "page" => Operation {
+            summary: "Gets a page of a response, with the answers already given on it.",
+            parameters: &[("id", "string"), ("token", "string"), ("page", "integer")],
+            request: None,
+            status: 200,
+            response: gen.subschema_for::<PageView>(),
+            errors: &[404, 409],
+        },
+        "post_page" => Operation {
+            summary: "Answers a page of a response, returning the next page or completing the response.",
+            parameters: &[("id", "string"), ("token", "string"), ("page", "integer")],
+            request: Some(gen.subschema_for::<PageAnswers>()),
+            status: 200,
+            response: gen.subschema_for::<ResponseInfo>(),
+            errors: &[400, 404, 409, 422],
+        },
+        "feedback" => Operation {
+            summary: "Gets the scored feedback of a completed response.",
+            parameters: &[("id", "string"), ("token", "string")],
+            request: None,
+            status: 200,
+            response: gen.subschema_for::<FeedbackView>(),
+            errors: &[404, 409],
+        },
+        _ => return None,
+    };
+    Some(operation)
+}
+
+fn content(schema: &Schema) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+fn reason(status: u16) -> &'static str {
+    Status::from_code(status).and_then(|status| status.reason()).unwrap_or("Error")
+}
+
+// The path parameters of a route, and its path with them written the OpenAPI way. A parameter
+// whose type isn't given is a string.
+fn parameters(path: &str, types: &[(&str, &str)]) -> (String, Vec<Value>) {
+    let mut parameters = vec![];
+    let segments: Vec<String> = path.split('/').map(|segment| {
+        match segment.strip_prefix('<').and_then(|segment| segment.strip_suffix('>')) {
+            Some(name) => {
+                let kind = types.iter().find(|(param, _)| *param == name).map_or("string", |(_, kind)| *kind);
+                parameters.push(json!({ "name": name, "in": "path", "required": true, "schema": { "type": kind } }));
+                format!("{{{}}}", name)
+            }
+            None => segment.to_string(),
+        }
+    }).collect();
+    (segments.join("/"), parameters)
+}
+
+/// The document of the API routes among `routes`.
+pub fn document<'a>(routes: impl Iterator<Item = &'a Route>) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    gen.subschema_for::<Answer>();
+    let error = gen.subschema_for::<ErrorBody>();
+    let mut paths = Map::new();
+    for route in routes.filter(|route| route.uri.base() == API_BASE) {
+        let name = route.name.as_deref().unwrap_or_default();
+        let described = describe(name, &mut gen);
+        let types = described.as_ref().map_or(&[][..], |described| described.parameters);
+        let (path, parameters) = parameters(&route.uri.path()[API_BASE.len()..], types);
+        let mut operation = json!({
+            "operationId": name,
+            "parameters": parameters,
+            "responses": { "default": { "description": "Any other error", "content": content(&error) } },
+        });
+        if let Some(described) = described {
+            operation["summary"] = json!(described.summary);
+            if let Some(request) = &described.request {
+                operation["requestBody"] = json!({ "required": true, "content": content(request) });
+            }
+            let responses = &mut operation["responses"];
+            responses[described.status.to_string()] = json!({ "description": reason(described.status), "content": content(&described.response) });
+            for status in described.errors {
+                responses[status.to_string()] = json!({ "description": reason(*status), "content": content(&error) });
+            }
+        }
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        item[route.method.as_str().to_lowercase()] = operation;
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "Survey-Data API", "version": "1" },
+        "servers": [{ "url": API_BASE }],
+        "paths": paths,
+        "components": { "schemas": gen.definitions() },
+    })
+}
+
+/// Generates the document from the mounted routes once the server is built.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("OpenAPI", |rocket| Box::pin(async move {
+        let document = document(rocket.routes());
+        rocket.manage(Document(document))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::postgres::PgPoolOptions;
+    use super::*;
+
+    // Every route the server mounts under the API's base is described, with the parameters its
+    // path has. The pool never connects, as no request is made.
+    #[rocket::async_test]
+    async fn every_api_route_is_described() {
+        let pool = PgPoolOptions::new().connect_lazy("postgres://localhost/unused").unwrap();
+        let rocket = crate::build(pool);
+        let mut gen = SchemaSettings::openapi3().into_generator();
+        let routes: Vec<&Route> = rocket.routes().filter(|route| route.uri.base() == API_BASE).collect();
+        assert!(!routes.is_empty());
+        for route in routes {
+            let name = route.name.as_deref().unwrap_or_default();
+            let described = describe(name, &mut gen).unwrap_or_else(|| panic!("{} isn't described", name));
+            let (_, parameters) = parameters(&route.uri.path()[API_BASE.len()..], &[]);
+            let names: Vec<&str> = parameters.iter().map(|parameter| parameter["name"].as_str().unwrap()).collect();
+            let described: Vec<&str> = described.parameters.iter().map(|(name, _)| *name).collect();
+            assert_eq!(names, described, "the parameters of {}", name);
+        }
+    }
+}
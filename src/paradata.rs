@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde_json::{json, Value};
+use crate::util::now;
+
+// Paradata describes how a response was given rather than what was answered. It is kept in its
+// own column, shaped like
+//     {"user_agent": "...", "viewport": "small",
+//      "pages": {"0": {"served": 1634567890.1, "views": 1, "submitted": 1634567921.5}}}
+// with timestamps in seconds since the Unix epoch, taken on the server.
+
+const VIEWPORT_CLASSES: [&str; 3] = ["small", "medium", "large"];
+
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        Outcome::Success(ClientInfo {
+            user_agent: req.headers().get_one("User-Agent").map(String::from),
+        })
+    }
+}
+
+fn page_entry(paradata: &mut Value, page: usize) -> &mut Value {
+    if !paradata["pages"].is_object() {
+        paradata["pages"] = json!({});
+    }
+    let entry = &mut paradata["pages"][page.to_string()];
+    if entry.is_null() {
+        *entry = json!({});
+    }
+    entry
+}
+
+/// Records that a page was shown. Only the first time it is served is kept, along with a count
+/// of how often it was viewed.
+pub fn served(paradata: &mut Value, page: usize, client: &ClientInfo) {
+    if let Some(user_agent) = &client.user_agent {
+        paradata["user_agent"] = json!(user_agent);
+    }
+    let entry = page_entry(paradata, page);
+    if entry["served"].is_null() {
+        entry["served"] = json!(now());
+    }
+    entry["views"] = json!(entry["views"].as_u64().unwrap_or(0) + 1);
+}
+
+/// Records that a page was submitted, together with the paradata fields sent by the browser.
+pub fn submitted(paradata: &mut Value, page: usize, fields: &HashMap<String, String>) {
+    if let Some(viewport) = fields.get("viewport").filter(|v| VIEWPORT_CLASSES.contains(&v.as_str())) {
+        paradata["viewport"] = json!(viewport);
+    }
+    page_entry(paradata, page)["submitted"] = json!(now());
+}
+
+/// Seconds from first serving the first page to the last submission, if the response has both.
+pub fn completion_seconds(paradata: &Value) -> Option<f64> {
+    let pages = paradata["pages"].as_object()?;
+    let start = pages.values().filter_map(|p| p["served"].as_f64()).reduce(f64::min)?;
+    let end = pages.values().filter_map(|p| p["submitted"].as_f64()).reduce(f64::max)?;
+    Some(end - start)
+}
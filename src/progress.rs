@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use rocket::fairing::AdHoc;
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use serde_json::Value;
+use sqlx::{PgPool, Postgres};
+use sqlx::pool::PoolConnection;
+use crate::database;
+use crate::tests::{all_tests, Test};
+
+// Progress through a test, shown on each of its pages. Progress counts the pages that will be
+// shown with the answers so far, so pages left out by a condition don't count; a page whose
+// condition depends on a question that hasn't been answered yet counts as left out until it is.
+// The time left is estimated from the median time past respondents took to complete the test,
+// which is recomputed in the background rather than on every page view.
+
+// The number of completed responses needed before the median time to complete a test is used.
+const MIN_COMPLETIONS: i64 = 10;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Progress {
+    /// The position of the current page among the shown pages, from 1.
+    pub page: usize,
+    pub pages: usize,
+    /// The percentage of the shown pages already completed.
+    pub percentage: f64,
+    pub minutes_left: Option<u64>,
+}
+
+/// The estimated time in seconds to complete each test that has an estimate, by test id, as last
+/// refreshed. It is managed by the server and kept up to date in the background by `fairing`.
+#[derive(Clone, Default)]
+pub struct Durations(Arc<RwLock<HashMap<String, f64>>>);
+
+impl Durations {
+    /// The estimated time to complete the test in seconds, once enough respondents completed it.
+    pub fn of(&self, test: &Test) -> Option<f64> {
+        self.0.read().unwrap().get(&test.id).copied()
+    }
+}
+
+/// Recomputes the estimated time to complete each test: the median over the completed responses
+/// to it, if there are enough.
+pub async fn refresh_durations(durations: &Durations, conn: &mut PoolConnection<Postgres>) {
+    for test in all_tests() {
+        let (median, completions) = database::get_median_duration(&test.id, conn).await;
+        let mut durations = durations.0.write().unwrap();
+        match median.filter(|_| completions >= MIN_COMPLETIONS) {
+            Some(median) => durations.insert(test.id.clone(), median),
+            None => durations.remove(&test.id),
+        };
+    }
+}
+
+/// Refreshes the estimated durations when the server starts and then every hour.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Estimated durations", |rocket| Box::pin(async move {
+        let pool = rocket.state::<PgPool>().unwrap().clone();
+        let durations = rocket.state::<Durations>().unwrap().clone();
+        tokio::spawn(async move {
+            loop {
+                match pool.acquire().await {
+                    Ok(mut conn) => refresh_durations(&durations, &mut conn).await,
+                    Err(e) => error!("Couldn't refresh the estimated durations: {}", e),
+                }
+                tokio::time::sleep(REFRESH_INTERVAL).await;
+            }
+        });
+    }))
+}
+
+/// A duration in seconds as whole minutes, rounded up and at least one.
+pub fn minutes(seconds: f64) -> u64 {
+    (seconds / 60.0).ceil().max(1.0) as u64
+}
+
+pub fn progress(test: &Test, page: usize, resp: &HashMap<String, Value>, duration: Option<f64>) -> Progress {
+    let shown = |p: usize| p == page || test.pages[p].condition.holds(resp) == Some(true);
+    let done = (0..page).filter(|p| shown(*p)).count();
+    let pages = (0..test.pages.len()).filter(|p| shown(*p)).count();
+    let left = (pages - done) as f64 / pages as f64;
+    Progress {
+        page: done + 1,
+        pages,
+        percentage: 100.0 * done as f64 / pages as f64,
+        minutes_left: duration.map(|duration| minutes(duration * left)),
+    }
+}
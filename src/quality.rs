@@ -0,0 +1,269 @@
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use crate::database;
+use crate::tests::{QuestionContent, Test};
+
+// Indices of careless or inattentive responding, computed over the Likert items of a test. The
+// psychometric synonyms and antonyms and the Mahalanobis distance depend on the whole sample, so
+// responses are always assessed together.
+
+// Item pairs correlating at least this strongly in the sample count as psychometric synonyms, and
+// pairs correlating at least as strongly negatively as antonyms.
+const SYNONYM_CORRELATION: f64 = 0.4;
+const ANTONYM_CORRELATION: f64 = -0.4;
+// A respondent's consistency is only computed over at least this many pairs they answered.
+const MIN_PAIRS: usize = 3;
+// Flag when the longest run of identical answers covers more than this share of the items.
+const MAX_LONGSTRING_SHARE: f64 = 0.5;
+const MIN_IRV: f64 = 0.5;
+// Answering a Likert item in under two seconds is not plausible for an attentive reader.
+const MIN_SECONDS_PER_ITEM: f64 = 2.0;
+// z for the 99.9th percentile, used for the chi-square cutoff on the squared Mahalanobis distance.
+const MAHALANOBIS_Z: f64 = 3.090;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Quality {
+    pub longstring: usize,
+    pub irv: Option<f64>,
+    #[serde(default)]
+    pub synonym_consistency: Option<f64>,
+    pub antonym_consistency: Option<f64>,
+    pub mahalanobis: Option<f64>,
+    pub seconds_per_item: Option<f64>,
+    pub attention_failed: usize,
+    pub flags: Vec<String>,
+}
+
+impl Quality {
+    pub fn flagged(quality: &Value) -> bool {
+        quality["flags"].as_array().is_some_and(|flags| !flags.is_empty())
+    }
+}
+
+/// The ids of the test's ordinal items, in the order they are presented.
+pub fn likert_items(test: &Test) -> Vec<&str> {
+    test.pages.iter()
+        .flat_map(|page| &page.elements)
+        .filter(|q| matches!(q.content, QuestionContent::McQuestion { expected: None, .. }))
+        .map(|q| q.id.as_str())
+        .collect()
+}
+
+fn mean(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+fn sd(xs: &[f64]) -> f64 {
+    let m = mean(xs);
+    (xs.iter().map(|x| (x - m).powi(2)).sum::<f64>() / xs.len() as f64).sqrt()
+}
+
+pub fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    let (mx, my) = (mean(xs), mean(ys));
+    let cov: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mx) * (y - my)).sum();
+    let vx: f64 = xs.iter().map(|x| (x - mx).powi(2)).sum();
+    let vy: f64 = ys.iter().map(|y| (y - my).powi(2)).sum();
+    if vx == 0.0 || vy == 0.0 {
+        None
+    }
+    else {
+        Some(cov / (vx * vy).sqrt())
+    }
+}
+
+fn longstring(answers: &[Option<f64>]) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev = None;
+    for answer in answers {
+        run = if answer.is_some() && *answer == prev { run + 1 } else { 1 };
+        prev = *answer;
+        if answer.is_some() {
+            longest = longest.max(run);
+        }
+    }
+    longest
+}
+
+// Gauss-Jordan elimination; `None` if the matrix is singular.
+fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut a: Vec<Vec<f64>> = matrix.iter().enumerate().map(|(i, row)| {
+        let mut row = row.clone();
+        row.extend((0..n).map(|j| if i == j { 1.0 } else { 0.0 }));
+        row
+    }).collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|x, y| a[*x][col].abs().partial_cmp(&a[*y][col].abs()).unwrap())?;
+        if a[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        a.swap(col, pivot);
+        let p = a[col][col];
+        for x in &mut a[col] {
+            *x /= p;
+        }
+        for row in 0..n {
+            if row != col {
+                let factor = a[row][col];
+                let pivot_row = a[col].clone();
+                for (x, y) in a[row].iter_mut().zip(pivot_row) {
+                    *x -= factor * y;
+                }
+            }
+        }
+    }
+    Some(a.into_iter().map(|row| row[n..].to_vec()).collect())
+}
+
+// The correlation, within one respondent, between the answers to the first and the second items
+// of the pairs they answered both of. Attentive respondents answer synonyms alike and antonyms
+// oppositely.
+fn consistency(pairs: &[(usize, usize)], row: &[Option<f64>]) -> Option<f64> {
+    let (xs, ys): (Vec<f64>, Vec<f64>) = pairs.iter()
+        .filter_map(|(i, j)| Some((row[*i]?, row[*j]?)))
+        .unzip();
+    if xs.len() >= MIN_PAIRS { pearson(&xs, &ys) } else { None }
+}
+
+// Wilson-Hilferty approximation of the chi-square quantile with `k` degrees of freedom.
+fn chi_square_cutoff(k: usize) -> f64 {
+    let k = k as f64;
+    k * (1.0 - 2.0 / (9.0 * k) + MAHALANOBIS_Z * (2.0 / (9.0 * k)).sqrt()).powi(3)
+}
+
+/// Assesses every response; `responses` holds each response's content and the number of seconds
+/// between starting and submitting it.
+pub fn assess(test: &Test, responses: &[(Value, Option<f64>)]) -> Vec<Quality> {
+    let items = likert_items(test);
+    let checks: Vec<&str> = test.pages.iter()
+        .flat_map(|page| &page.elements)
+        .filter(|q| q.is_attention_check())
+        .map(|q| q.id.as_str())
+        .collect();
+    let k = items.len();
+    let rows: Vec<Vec<Option<f64>>> = responses.iter()
+        .map(|(resp, _)| items.iter().map(|id| resp[*id]["ord"].as_f64()).collect())
+        .collect();
+    let complete: Vec<Vec<f64>> = rows.iter()
+        .filter(|row| row.iter().all(Option::is_some))
+        .map(|row| row.iter().flatten().copied().collect())
+        .collect();
+    let column = |i: usize| -> Vec<f64> { complete.iter().map(|row| row[i]).collect() };
+    let means: Vec<f64> = (0..k).map(|i| mean(&column(i))).collect();
+    let mut synonyms = vec![];
+    let mut antonyms = vec![];
+    let mut cov = vec![vec![0.0; k]; k];
+    if complete.len() > k {
+        for i in 0..k {
+            for j in 0..k {
+                let (xi, xj) = (column(i), column(j));
+                cov[i][j] = xi.iter().zip(&xj).map(|(x, y)| (x - means[i]) * (y - means[j])).sum::<f64>()
+                    / (complete.len() - 1) as f64;
+                match pearson(&xi, &xj) {
+                    Some(r) if i < j && r >= SYNONYM_CORRELATION => synonyms.push((i, j)),
+                    Some(r) if i < j && r <= ANTONYM_CORRELATION => antonyms.push((i, j)),
+                    _ => {}
+                }
+            }
+        }
+    }
+    let inverse = if complete.len() > k { invert(&cov) } else { None };
+    rows.iter().zip(responses).map(|(row, (resp, seconds))| {
+        let answered: Vec<f64> = row.iter().flatten().copied().collect();
+        let mut quality = Quality {
+            longstring: longstring(row),
+            irv: if answered.len() > 1 { Some(sd(&answered)) } else { None },
+            synonym_consistency: consistency(&synonyms, row),
+            antonym_consistency: consistency(&antonyms, row),
+            mahalanobis: None,
+            seconds_per_item: seconds.filter(|_| !answered.is_empty()).map(|s| s / answered.len() as f64),
+            attention_failed: checks.iter().filter(|id| resp[**id]["passed"] == false).count(),
+            flags: vec![],
+        };
+        if let (Some(inverse), true) = (&inverse, answered.len() == k) {
+            let diff: Vec<f64> = answered.iter().zip(&means).map(|(x, m)| x - m).collect();
+            let d2: f64 = (0..k).flat_map(|i| (0..k).map(move |j| (i, j)))
+                .map(|(i, j)| diff[i] * inverse[i][j] * diff[j])
+                .sum();
+            quality.mahalanobis = Some(d2.sqrt());
+        }
+        if k > 0 && quality.longstring as f64 > MAX_LONGSTRING_SHARE * k as f64 {
+            quality.flags.push("longstring".into());
+        }
+        if quality.irv.is_some_and(|irv| irv < MIN_IRV) {
+            quality.flags.push("irv".into());
+        }
+        if quality.synonym_consistency.is_some_and(|r| r < 0.0) {
+            quality.flags.push("synonyms".into());
+        }
+        if quality.antonym_consistency.is_some_and(|r| r > 0.0) {
+            quality.flags.push("antonyms".into());
+        }
+        if quality.mahalanobis.is_some_and(|d| d * d > chi_square_cutoff(k)) {
+            quality.flags.push("mahalanobis".into());
+        }
+        if quality.seconds_per_item.is_some_and(|s| s < MIN_SECONDS_PER_ITEM) {
+            quality.flags.push("speed".into());
+        }
+        if quality.attention_failed > 0 {
+            quality.flags.push("attention".into());
+        }
+        quality
+    }).collect()
+}
+
+/// Recomputes and stores the quality indices of every response that answered the test's items.
+pub async fn assess_and_store(test: &Test, conn: &mut PoolConnection<Postgres>) {
+    let items = likert_items(test);
+    let responses: Vec<_> = database::get_quality_inputs(&test.id, conn).await.into_iter()
+        .filter(|(_, resp, _)| items.iter().any(|id| !resp[*id].is_null()))
+        .collect();
+    let inputs: Vec<(Value, Option<f64>)> = responses.iter()
+        .map(|(_, resp, seconds)| (resp.clone(), *seconds))
+        .collect();
+    for ((response_id, _, _), quality) in responses.iter().zip(assess(test, &inputs)) {
+        database::set_quality(*response_id, serde_json::to_value(&quality).unwrap(), conn).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::tests::make_tipi_test;
+    use super::*;
+
+    #[test]
+    fn longstring_counts_the_longest_run() {
+        assert_eq!(longstring(&[]), 0);
+        assert_eq!(longstring(&[None, None]), 0);
+        assert_eq!(longstring(&[Some(1.0), Some(2.0), Some(3.0)]), 1);
+        assert_eq!(longstring(&[Some(4.0), Some(4.0), Some(4.0), Some(2.0), Some(2.0)]), 3);
+        // An unanswered item ends a run.
+        assert_eq!(longstring(&[Some(1.0), Some(1.0), None, Some(1.0), Some(1.0)]), 2);
+    }
+
+    #[test]
+    fn irv_is_the_standard_deviation() {
+        assert_eq!(sd(&[3.0, 3.0, 3.0, 3.0]), 0.0);
+        assert!((sd(&[1.0, 2.0, 3.0, 4.0]) - 1.25f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn straightlining_is_flagged() {
+        let test = make_tipi_test();
+        let items = likert_items(&test);
+        let same: Value = items.iter().map(|id| (id.to_string(), json!({"ord": 3}))).collect::<serde_json::Map<_, _>>().into();
+        let varied: Value = items.iter().enumerate().map(|(i, id)| (id.to_string(), json!({"ord": i % 7}))).collect::<serde_json::Map<_, _>>().into();
+        let qualities = assess(&test, &[(same, None), (varied, None)]);
+        assert_eq!(qualities[0].longstring, items.len());
+        assert_eq!(qualities[0].irv, Some(0.0));
+        assert!(qualities[0].flags.contains(&"longstring".to_string()));
+        assert!(qualities[0].flags.contains(&"irv".to_string()));
+        assert_eq!(qualities[1].longstring, 1);
+        assert!(qualities[1].flags.is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs;
+use rocket::futures::StreamExt;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use crate::codebook::Codebook;
+use crate::database;
+use crate::dataset::{delimited_line, Cell, Column, Dataset, ExportFilter, Kind, Source};
+use crate::tests::Test;
+use crate::util::{format_timestamp, now};
+
+// Public datasets, released as versioned bundles under ./releases/<test>/v<N>/. A release holds
+// only consented responses, without response ids, timestamps, private questions or free text,
+// and with the test's demographic questions coarsened to k-anonymity: every combination of
+// demographic answers that appears in the data is shared by at least k respondents.
+
+pub const DEFAULT_K: usize = 5;
+
+pub struct Release {
+    pub version: usize,
+    pub dir: String,
+    pub responses: usize,
+    pub suppressed: usize,
+    pub dropped: usize,
+}
+
+fn publishable(test: &Test, column: &Column) -> bool {
+    let private = column.question().is_some_and(|id| test.private.iter().any(|private| private == id));
+    !private && !matches!(column.source,
+        Source::ResponseId | Source::StartTime | Source::SubmitTime
+        | Source::Nominal(_) | Source::Other(_) | Source::Answer(_))
+}
+
+fn is_demographic(test: &Test, column: &Column) -> bool {
+    column.question().is_some_and(|id| test.demographics.iter().any(|demographic| demographic == id))
+}
+
+// Pools the categories of column `i` that fewer than `k` rows chose into the "Other" category
+// (added if the question has none), and clears the pooled category if it is still too small.
+// Returns the labels of the categories that were pooled or cleared.
+fn pool_rare(column: &mut Column, i: usize, rows: &mut [Vec<Cell>], k: usize) -> Vec<String> {
+    let code = |cell: &Cell| match cell {
+        Cell::Integer(n) => Some(*n),
+        Cell::Boolean(b) => Some(*b as i64),
+        _ => None,
+    };
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for row in rows.iter() {
+        if let Some(n) = code(&row[i]) {
+            *counts.entry(n).or_insert(0) += 1;
+        }
+    }
+    let other = column.value_labels.iter().find(|(_, label)| label == "Other").map(|(value, _)| *value);
+    let rare: Vec<i64> = counts.iter()
+        .filter(|(n, count)| **count < k && Some(**n) != other)
+        .map(|(n, _)| *n)
+        .collect();
+    if rare.is_empty() && other.is_none_or(|other| counts.get(&other).is_none_or(|count| *count >= k)) {
+        return vec![];
+    }
+    let other = other.unwrap_or_else(|| column.value_labels.iter().map(|(value, _)| value + 1).max().unwrap_or(0));
+    let pooled_count: usize = rare.iter().chain([other].iter()).filter_map(|n| counts.get(n)).sum();
+    let pooled: Vec<String> = column.value_labels.iter()
+        .filter(|(value, _)| rare.contains(value) || (pooled_count < k && *value == other))
+        .map(|(_, label)| label.clone())
+        .collect();
+    for row in rows.iter_mut() {
+        if code(&row[i]).is_some_and(|n| rare.contains(&n) || n == other) {
+            row[i] = if pooled_count < k { Cell::Missing } else { Cell::Integer(other) };
+        }
+    }
+    column.kind = Kind::Integer;
+    column.value_labels.retain(|(value, _)| !rare.contains(value) && *value != other);
+    if pooled_count >= k {
+        column.value_labels.push((other, "Other".into()));
+    }
+    pooled
+}
+
+// Clears the demographic answers of rows whose combination of them is shared by fewer than `k`
+// rows, and then drops the rows that are still not k-anonymous. Returns the number of rows
+// cleared and dropped.
+fn suppress(demographics: &[usize], rows: &mut Vec<Vec<Cell>>, k: usize) -> (usize, usize) {
+    let key = |row: &Vec<Cell>| -> Vec<String> { demographics.iter().map(|i| row[*i].to_text()).collect() };
+    let counts = |rows: &Vec<Vec<Cell>>| {
+        let mut counts: HashMap<Vec<String>, usize> = HashMap::new();
+        for row in rows {
+            *counts.entry(key(row)).or_insert(0) += 1;
+        }
+        counts
+    };
+    let before = counts(rows);
+    let mut suppressed = 0;
+    for row in rows.iter_mut() {
+        if before[&key(row)] < k {
+            for i in demographics {
+                row[*i] = Cell::Missing;
+            }
+            suppressed += 1;
+        }
+    }
+    let after = counts(rows);
+    let total = rows.len();
+    rows.retain(|row| after[&key(row)] >= k);
+    (suppressed, total - rows.len())
+}
+
+fn next_version(test: &Test) -> usize {
+    let existing = fs::read_dir(format!("./releases/{}", test.id)).into_iter().flatten()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_prefix('v')?.parse::<usize>().ok());
+    existing.max().unwrap_or(0) + 1
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Builds and writes the next release of a test's public dataset.
+pub async fn build(test: &Test, k: usize, conn: &mut PoolConnection<Postgres>) -> Release {
+    let filter = ExportFilter { consented: true, ..ExportFilter::default() };
+    let mut dataset = Dataset::new(test, false);
+    dataset.columns.retain(|column| publishable(test, column));
+    let mut records: Vec<_> = database::stream_records(test, &filter, conn).collect().await;
+    // Response ids are random, so ordering by them hides the order in which responses came in.
+    records.sort_by_key(|record| record.response_id);
+    let mut rows: Vec<Vec<Cell>> = records.iter().map(|record| dataset.row(record)).collect();
+
+    let demographics: Vec<usize> = (0..dataset.columns.len())
+        .filter(|i| is_demographic(test, &dataset.columns[*i]))
+        .collect();
+    let mut pooled = serde_json::Map::new();
+    for i in &demographics {
+        let categories = pool_rare(&mut dataset.columns[*i], *i, &mut rows, k);
+        if !categories.is_empty() {
+            pooled.insert(dataset.columns[*i].name.clone(), json!(categories));
+        }
+    }
+    let (suppressed, dropped) = suppress(&demographics, &mut rows, k);
+
+    let version = next_version(test);
+    let dir = format!("./releases/{}/v{}", test.id, version);
+    let names: Vec<String> = dataset.columns.iter().map(|column| column.name.clone()).collect();
+    let mut data = delimited_line(&names, ',');
+    for row in &rows {
+        let cells: Vec<String> = row.iter().map(Cell::to_text).collect();
+        data += &delimited_line(&cells, ',');
+    }
+    let codebook = Codebook::from_columns(test, &dataset.columns);
+    let readme = format!(
+        "# {} (public release v{})\n\n\
+         Responses from respondents who consented to their response being published, built on {}.\n\n\
+         - `data.csv`: one row per response, in no particular order; variables are described in \
+         `codebook.md` and `codebook.xml` (DDI-Codebook 2.5).\n\
+         - Response ids, timestamps, private questions and free-text answers are not included.\n\
+         - Demographic variables ({}) are {}-anonymous: rare categories are pooled into \"Other\", \
+         answers are cleared where a combination remained rarer than that, and responses that \
+         still could not be made anonymous are left out. `manifest.json` lists what was changed.\n\
+         - `SHA256SUMS` holds the checksums of the other files; check them with `sha256sum -c SHA256SUMS`.\n",
+        test.name, version, format_timestamp(now()),
+        test.demographics.join(", "), k);
+    let mut files = vec![
+        ("data.csv", data.into_bytes()),
+        ("codebook.md", codebook.markdown().into_bytes()),
+        ("codebook.xml", codebook.ddi().into_bytes()),
+        ("README.md", readme.into_bytes()),
+    ];
+    let manifest = json!({
+        "test": test.id,
+        "version": version,
+        "created": format_timestamp(now()),
+        "k": k,
+        "responses": rows.len(),
+        "pooled_categories": pooled,
+        "suppressed_responses": suppressed,
+        "dropped_responses": dropped,
+        "files": files.iter().map(|(name, bytes)| (name.to_string(), json!(sha256(bytes)))).collect::<serde_json::Map<_, _>>(),
+    });
+    files.push(("manifest.json", serde_json::to_string_pretty(&manifest).unwrap().into_bytes()));
+    let sums: String = files.iter().map(|(name, bytes)| format!("{}  {}\n", sha256(bytes), name)).collect();
+    files.push(("SHA256SUMS", sums.into_bytes()));
+
+    fs::create_dir_all(&dir).unwrap();
+    for (name, bytes) in &files {
+        fs::write(format!("{}/{}", dir, name), bytes).unwrap();
+    }
+    Release { version, dir, responses: rows.len(), suppressed, dropped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(labels: &[&str]) -> Column {
+        Column {
+            name: "q".into(),
+            kind: Kind::Integer,
+            label: "Question".into(),
+            value_labels: labels.iter().enumerate().map(|(i, label)| (i as i64, label.to_string())).collect(),
+            source: Source::Ordinal("q".into()),
+        }
+    }
+
+    fn rows(codes: &[i64]) -> Vec<Vec<Cell>> {
+        codes.iter().map(|n| vec![Cell::Integer(*n)]).collect()
+    }
+
+    #[test]
+    fn pools_rare_categories_into_other() {
+        let mut column = column(&["A", "B", "C", "D"]);
+        let mut rows = rows(&[0, 0, 0, 1, 1, 1, 2, 3, 3]);
+        assert_eq!(pool_rare(&mut column, 0, &mut rows, 3), ["C", "D"]);
+        assert_eq!(column.value_labels, [(0, "A".to_string()), (1, "B".to_string()), (4, "Other".to_string())]);
+        assert_eq!(rows[6..], [[Cell::Integer(4)], [Cell::Integer(4)], [Cell::Integer(4)]]);
+    }
+
+    #[test]
+    fn clears_a_pool_that_is_still_too_small() {
+        let mut column = column(&["A", "B", "Other"]);
+        let mut rows = rows(&[0, 0, 0, 1, 2]);
+        assert_eq!(pool_rare(&mut column, 0, &mut rows, 3), ["B", "Other"]);
+        assert_eq!(column.value_labels, [(0, "A".to_string())]);
+        assert_eq!(rows[3..], [[Cell::Missing], [Cell::Missing]]);
+    }
+
+    #[test]
+    fn leaves_common_categories_alone() {
+        let mut column = column(&["A", "B"]);
+        let mut rows = rows(&[0, 0, 1, 1]);
+        assert!(pool_rare(&mut column, 0, &mut rows, 2).is_empty());
+        assert_eq!(column.value_labels.len(), 2);
+        assert_eq!(rows, self::rows(&[0, 0, 1, 1]));
+    }
+
+    #[test]
+    fn suppresses_rare_combinations() {
+        let mut rows = vec![
+            vec![Cell::Integer(0), Cell::Integer(0), Cell::Float(1.0)],
+            vec![Cell::Integer(0), Cell::Integer(0), Cell::Float(2.0)],
+            vec![Cell::Integer(0), Cell::Integer(1), Cell::Float(3.0)],
+            vec![Cell::Integer(1), Cell::Integer(1), Cell::Float(4.0)],
+            vec![Cell::Integer(1), Cell::Integer(1), Cell::Float(5.0)],
+        ];
+        // The one respondent with (0, 1) loses their demographics, and is then alone without them.
+        assert_eq!(suppress(&[0, 1], &mut rows, 2), (1, 1));
+        assert_eq!(rows.iter().map(|row| row[2].clone()).collect::<Vec<_>>(),
+                   [Cell::Float(1.0), Cell::Float(2.0), Cell::Float(4.0), Cell::Float(5.0)]);
+
+        let mut rows = vec![vec![Cell::Integer(0)], vec![Cell::Integer(1)], vec![Cell::Integer(2)]];
+        assert_eq!(suppress(&[0], &mut rows, 2), (3, 0));
+        assert!(rows.iter().all(|row| row[0] == Cell::Missing));
+    }
+}
@@ -0,0 +1,33 @@
+use uuid::Uuid;
+use crate::signing;
+
+// Attempt tokens. The pages of an attempt at a test are addressed by the response id and a token
+// signing it, so an attempt doesn't depend on a cookie. Every page also shows a resume link with
+// the token, which continues the attempt on another device: following it sets the response cookie
+// there and opens the last page reached, with the answers entered so far filled in.
+
+const TOKEN_PURPOSE: &str = "resume";
+
+pub fn token(response_id: Uuid) -> String {
+    signing::sign(TOKEN_PURPOSE, &response_id.to_string())
+}
+
+pub fn verify(response_id: Uuid, token: &str) -> bool {
+    signing::verify(TOKEN_PURPOSE, &response_id.to_string(), token)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sharing;
+    use super::*;
+
+    #[test]
+    fn tokens_open_only_their_attempt() {
+        let token = token(Uuid::from_u128(1));
+        assert!(verify(Uuid::from_u128(1), &token));
+        assert!(!verify(Uuid::from_u128(2), &token));
+        assert!(!verify(Uuid::from_u128(1), &token[1..]));
+        // The feedback token of the response doesn't continue the attempt.
+        assert!(!verify(Uuid::from_u128(1), &sharing::feedback_token(Uuid::from_u128(1))));
+    }
+}
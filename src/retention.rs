@@ -0,0 +1,202 @@
+use std::fmt;
+use std::time::Duration;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres};
+use sqlx::pool::PoolConnection;
+use crate::database;
+use crate::tests::{get_test, QuestionContent, Test};
+
+// Retention policy. Every page view of a new respondent creates a response row, so most rows are
+// abandoned early. Incomplete responses not touched for a while are purged, or archived into
+// `archived_responses` so that the dropout funnel can still be reconstructed from them. Free-text
+// answers, which may say anything about anyone, are removed from all responses after their own
+// period, archived ones included. The policy is configured in the `retention` table of Rocket.toml, e.g.
+//     [default.retention]
+//     incomplete_days = 30
+//     incomplete_action = "archive"
+//     text_days = 365
+//     interval_hours = 24
+//     dry_run = false
+// and applied by the server at launch and every `interval_hours` after. With `dry_run` set, the
+// server only reports what the policy would do.
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Disposal {
+    Purge,
+    Archive,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Policy {
+    /// Days after their last submission before incomplete responses are disposed of; never if
+    /// unset.
+    pub incomplete_days: Option<i32>,
+    #[serde(default = "default_disposal")]
+    pub incomplete_action: Disposal,
+    /// Days after their last submission before free-text answers are removed; never if unset.
+    pub text_days: Option<i32>,
+    #[serde(default = "default_interval")]
+    pub interval_hours: u64,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_disposal() -> Disposal {
+    Disposal::Archive
+}
+
+fn default_interval() -> u64 {
+    24
+}
+
+impl Policy {
+    /// The configured policy, if there is one.
+    pub fn load(figment: &Figment) -> Option<Policy> {
+        if !figment.contains("retention") {
+            return None;
+        }
+        Some(figment.extract_inner("retention").unwrap_or_else(|e| panic!("Invalid retention policy: {}", e)))
+    }
+}
+
+/// What a run of the policy did, or would do in a dry run.
+pub struct Report {
+    pub dry_run: bool,
+    pub abandoned: Option<(Disposal, i32, u64)>,
+    pub texts: Option<(i32, usize)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = |done: &'static str, planned: &'static str| if self.dry_run { planned } else { done };
+        write!(f, "Retention{}:", if self.dry_run { " (dry run)" } else { "" })?;
+        if let Some((disposal, days, count)) = self.abandoned {
+            let action = match disposal {
+                Disposal::Purge => verb("purged", "would purge"),
+                Disposal::Archive => verb("archived", "would archive"),
+            };
+            write!(f, " {} {} incomplete responses older than {} days.", action, count, days)?;
+        }
+        if let Some((days, count)) = self.texts {
+            write!(f, " {} free text from {} responses older than {} days.",
+                   verb("Removed", "Would remove"), count, days)?;
+        }
+        if self.abandoned.is_none() && self.texts.is_none() {
+            write!(f, " nothing to do.")?;
+        }
+        Ok(())
+    }
+}
+
+// Replaces the free-text answers in a response with a marker; `true` if any of them had text.
+fn remove_text(test: &Test, content: &mut Value) -> bool {
+    let mut removed = false;
+    for (q, _) in test.questions() {
+        let answer = match content.get_mut(&q.id) {
+            Some(answer) if !answer.is_null() => answer,
+            _ => continue,
+        };
+        let has_text = answer["answer"].as_str().is_some_and(|text| !text.is_empty());
+        match q.content {
+            QuestionContent::TextAreaQuestion => *answer = json!({"removed": true}),
+            QuestionContent::McQuestionVert { other: true, .. } if answer["nom"] == "Other" =>
+                *answer = json!({"nom": "Other", "removed": true}),
+            _ => continue,
+        }
+        removed |= has_text;
+    }
+    removed
+}
+
+pub async fn apply(policy: &Policy, dry_run: bool, conn: &mut PoolConnection<Postgres>) -> Report {
+    let mut report = Report { dry_run, abandoned: None, texts: None };
+    if let Some(days) = policy.incomplete_days {
+        let count = match (dry_run, policy.incomplete_action) {
+            (true, _) => database::count_abandoned(days, conn).await as u64,
+            (false, Disposal::Purge) => database::purge_abandoned(days, conn).await,
+            (false, Disposal::Archive) => database::archive_abandoned(days, conn).await,
+        };
+        report.abandoned = Some((policy.incomplete_action, days, count));
+    }
+    if let Some(days) = policy.text_days {
+        let mut count = 0;
+        let stored = database::get_text_expired(days, conn).await.into_iter().map(|response| (false, response));
+        let archived = database::get_archived_text_expired(days, conn).await.into_iter().map(|response| (true, response));
+        for (in_archive, (response_id, test_id, mut content)) in stored.chain(archived) {
+            let test = match test_id.as_deref().and_then(get_test) {
+                Some(test) => test,
+                None => continue,
+            };
+            if remove_text(test, &mut content) {
+                count += 1;
+            }
+            match (dry_run, in_archive) {
+                (true, _) => {}
+                (false, false) => database::set_text_removed(response_id, content, conn).await,
+                (false, true) => database::set_archived_text_removed(response_id, content, conn).await,
+            }
+        }
+        report.texts = Some((days, count));
+    }
+    report
+}
+
+/// Applies the configured policy in the background while the server runs.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Retention", |rocket| Box::pin(async move {
+        let policy = match Policy::load(rocket.figment()) {
+            Some(policy) => policy,
+            None => return,
+        };
+        let pool = rocket.state::<PgPool>().unwrap().clone();
+        tokio::spawn(async move {
+            loop {
+                let report = apply(&policy, policy.dry_run, &mut pool.acquire().await.unwrap()).await;
+                info!("{}", report);
+                tokio::time::sleep(Duration::from_secs(policy.interval_hours * 3600)).await;
+            }
+        });
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::make_tipi_test;
+    use super::*;
+
+    #[test]
+    fn removes_only_free_text() {
+        let test = make_tipi_test();
+        let mut content = json!({
+            "comments": {"answer": "I live at 1 Main Street"},
+            "gender": {"nom": "Other", "answer": "Agender"},
+            "consent": {"checked": true},
+        });
+        assert!(remove_text(&test, &mut content));
+        assert_eq!(content, json!({
+            "comments": {"removed": true},
+            "gender": {"nom": "Other", "removed": true},
+            "consent": {"checked": true},
+        }));
+        // Removing it again finds nothing left to remove.
+        assert!(!remove_text(&test, &mut content));
+    }
+
+    #[test]
+    fn keeps_listed_options() {
+        let test = make_tipi_test();
+        let mut content = json!({"gender": {"nom": "Female"}, "comments": null});
+        let before = content.clone();
+        assert!(!remove_text(&test, &mut content));
+        assert_eq!(content, before);
+        // An empty comment is still replaced, but there was no text to remove.
+        let mut content = json!({"comments": {"answer": ""}});
+        assert!(!remove_text(&test, &mut content));
+        assert_eq!(content, json!({"comments": {"removed": true}}));
+    }
+}
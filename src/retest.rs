@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use rocket::http::{Cookie, CookieJar};
+use rocket::serde::Serialize;
+use serde_json::Value;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::accounts::User;
+use crate::database;
+use crate::quality::pearson;
+use crate::signing;
+use crate::tests::Test;
+use crate::util::format_timestamp;
+
+// Test-retest tracking. A respondent's attempts at a test form a series, identified by the id of
+// one of them, usually the first. Attempts of a logged-in user join the series automatically;
+// anonymous respondents join a new attempt to their earlier ones with the retest code shown on
+// the earlier feedback. A code only joins a series its respondent owns: one with an attempt whose
+// feedback was shown in the same browser, as remembered in a private cookie, or one with an
+// attempt of the logged-in user, so a code seen by someone else doesn't open the series to them.
+// Respondents see how their scores changed across the series, and researchers see how stable
+// each scale is between the first two attempts of every series.
+
+const CODE_PURPOSE: &str = "retest-code";
+// The number of attempts at a test whose feedback a browser remembers.
+const REMEMBERED_ATTEMPTS: usize = 20;
+
+/// An attempt's submit time in seconds and its content.
+type Attempt<'a> = (f64, &'a Value);
+
+/// Makes the response's retest code usable and returns it.
+pub async fn issue_code(response_id: Uuid, conn: &mut PoolConnection<Postgres>) -> String {
+    let code = signing::code(CODE_PURPOSE, &response_id.to_string());
+    database::set_retest_hash(response_id, &signing::code_hash(&code), conn).await;
+    code
+}
+
+fn attempts_cookie(test: &Test) -> String {
+    format!("attempts[{}]", test.id)
+}
+
+// The attempts at the test whose feedback was shown in this browser, oldest first.
+fn remembered(test: &Test, cookies: &CookieJar<'_>) -> Vec<Uuid> {
+    cookies.get_private(&attempts_cookie(test))
+        .map(|cookie| cookie.value().split(' ').filter_map(|id| id.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Remembers the response as one of the browser's own attempts at the test.
+pub fn remember(test: &Test, response_id: Uuid, cookies: &CookieJar<'_>) {
+    let mut attempts = remembered(test, cookies);
+    if !attempts.contains(&response_id) {
+        attempts.push(response_id);
+    }
+    let kept = &attempts[attempts.len().saturating_sub(REMEMBERED_ATTEMPTS)..];
+    let value: Vec<String> = kept.iter().map(Uuid::to_string).collect();
+    cookies.add_private(Cookie::new(attempts_cookie(test), value.join(" ")));
+}
+
+/// Joins the response to the series of the earlier response to the test with the retest code;
+/// `false` if there is none, or if the series isn't the respondent's own.
+pub async fn join(test: &Test, response_id: Uuid, code: &str, cookies: &CookieJar<'_>, user: Option<User>, conn: &mut PoolConnection<Postgres>) -> bool {
+    match database::find_series_by_retest_hash(&test.id, &signing::code_hash(code), conn).await {
+        Some(series_id) => {
+            let owner = user.map(|user| user.user_id);
+            database::join_series(response_id, series_id, &remembered(test, cookies), owner, conn).await
+        }
+        None => false,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Point {
+    pub date: String,
+    pub percentage: f64,
+    pub current: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Change {
+    pub scale: String,
+    pub points: Vec<Point>,
+    /// The change of the current attempt from the one before, in percentage points.
+    pub change: Option<f64>,
+}
+
+/// How each scale changed across the attempts of a series, if there are several.
+pub fn changes(test: &Test, attempts: &[(Uuid, f64, Value)], current: Uuid) -> Vec<Change> {
+    if attempts.len() < 2 {
+        return vec![];
+    }
+    test.scales.iter().map(|scale| {
+        let scored: Vec<(&Uuid, f64, f64)> = attempts.iter()
+            .filter_map(|(id, time, content)| Some((id, *time, test.scale_percentage(&scale.id, content)?)))
+            .collect();
+        let change = scored.iter().position(|(id, _, _)| **id == current)
+            .filter(|i| *i > 0)
+            .map(|i| scored[i].2 - scored[i - 1].2);
+        Change {
+            scale: scale.name.clone(),
+            points: scored.iter()
+                .map(|(id, time, percentage)| Point { date: format_timestamp(*time), percentage: *percentage, current: **id == current })
+                .collect(),
+            change,
+        }
+    }).collect()
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScaleRetest {
+    pub scale: String,
+    pub pairs: usize,
+    pub correlation: Option<f64>,
+    /// The mean change from the first attempt to the second, in scale points.
+    pub mean_change: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RetestReport {
+    pub series: usize,
+    pub mean_interval_days: Option<f64>,
+    pub scales: Vec<ScaleRetest>,
+}
+
+/// Test-retest statistics over the first two attempts of every series, from attempts as given by
+/// `database::get_retests`.
+pub fn report(test: &Test, attempts: &[(Uuid, f64, Value)]) -> RetestReport {
+    let mut series: BTreeMap<Uuid, Vec<Attempt>> = BTreeMap::new();
+    for (series_id, time, content) in attempts {
+        series.entry(*series_id).or_default().push((*time, content));
+    }
+    let pairs: Vec<(&Attempt, &Attempt)> = series.values()
+        .filter(|attempts| attempts.len() >= 2)
+        .map(|attempts| (&attempts[0], &attempts[1]))
+        .collect();
+    let intervals: Vec<f64> = pairs.iter().map(|(first, second)| (second.0 - first.0) / 86400.0).collect();
+    let scales = test.scales.iter().map(|scale| {
+        let (firsts, seconds): (Vec<f64>, Vec<f64>) = pairs.iter()
+            .filter_map(|(first, second)| Some((scale.mean(first.1)?, scale.mean(second.1)?)))
+            .unzip();
+        ScaleRetest {
+            scale: scale.name.clone(),
+            pairs: firsts.len(),
+            correlation: if firsts.len() >= 3 { pearson(&firsts, &seconds) } else { None },
+            mean_change: if firsts.is_empty() { None } else {
+                Some(firsts.iter().zip(&seconds).map(|(a, b)| b - a).sum::<f64>() / firsts.len() as f64)
+            },
+        }
+    }).collect();
+    RetestReport {
+        series: pairs.len(),
+        mean_interval_days: if intervals.is_empty() { None } else { Some(intervals.iter().sum::<f64>() / intervals.len() as f64) },
+        scales,
+    }
+}
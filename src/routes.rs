@@ -1,4 +1,5 @@
 use std::time::SystemTime;
+use rocket::http::{ContentType, Header};
 use rocket::serde::Serialize;
 use rocket_dyn_templates::tera::Tera;
 use tokio::fs::metadata;
@@ -7,6 +8,13 @@ pub mod index;
 pub mod statics;
 pub mod test;
 pub mod debug;
+pub mod admin;
+pub mod aggregates;
+pub mod withdraw;
+pub mod access;
+pub mod accounts;
+pub mod api;
+pub mod openapi;
 
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
@@ -22,6 +30,22 @@ pub struct DebugContext<'r> {
     body: &'r str,
 }
 
+/// A file download.
+#[derive(Responder)]
+pub struct Attachment {
+    inner: (ContentType, Vec<u8>),
+    disposition: Header<'static>,
+}
+
+impl Attachment {
+    pub fn new(content_type: ContentType, filename: &str, body: Vec<u8>) -> Attachment {
+        Attachment {
+            inner: (content_type, body),
+            disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename)),
+        }
+    }
+}
+
 pub async fn style_hash() -> String {
     let mod_time = metadata("./static/css/style.css").await.ok().unwrap().modified().ok().unwrap();
     format!("{:?}", mod_time.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos())
@@ -39,6 +63,22 @@ static DEBUG_TEMPLATE: &str = r#"
 pub fn customize(tera: &mut Tera) {
     tera.add_raw_template("test.html", test::TEST_TEMPLATE).unwrap();
     tera.add_raw_template("feedback.html", test::FEEDBACK_TEMPLATE).unwrap();
+    tera.add_raw_template("compare.html", test::COMPARE_TEMPLATE).unwrap();
+    tera.add_raw_template("shared.html", test::SHARED_TEMPLATE).unwrap();
+    tera.add_raw_template("informant_done.html", test::INFORMANT_DONE_TEMPLATE).unwrap();
     tera.add_raw_template("index.html", index::TEMPLATE).unwrap();
     tera.add_raw_template("debug.html", DEBUG_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_login.html", admin::LOGIN_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_index.html", admin::INDEX_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_quality.html", admin::QUALITY_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_funnel.html", admin::FUNNEL_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_codebook.html", admin::CODEBOOK_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_aggregates.html", admin::AGGREGATES_TEMPLATE).unwrap();
+    tera.add_raw_template("admin_retest.html", admin::RETEST_TEMPLATE).unwrap();
+    tera.add_raw_template("aggregates.html", aggregates::TEMPLATE).unwrap();
+    tera.add_raw_template("withdraw.html", withdraw::TEMPLATE).unwrap();
+    tera.add_raw_template("access.html", access::TEMPLATE).unwrap();
+    tera.add_raw_template("account.html", accounts::ACCOUNT_TEMPLATE).unwrap();
+    tera.add_raw_template("my_results.html", accounts::RESULTS_TEMPLATE).unwrap();
+    tera.add_raw_template("openapi.html", openapi::TEMPLATE).unwrap();
 }
\ No newline at end of file
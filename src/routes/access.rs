@@ -0,0 +1,102 @@
+use rocket::State;
+use rocket::http::ContentType;
+use rocket::serde::Serialize;
+use rocket_dyn_templates::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::access::{self, AccessReport};
+use super::{Attachment, TemplateContext, style_hash};
+
+pub static TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Your Data</h1>
+    <p>This is everything we store about your response. You can also
+    <a href="/access/{{ data.response_id }}/{{ data.token }}/json">download it as JSON</a>.</p>
+    <table class="admin-table">
+        <tr><th>Response</th><td>{{ data.response_id }}</td></tr>
+        {% if data.user_id %}<tr><th>User</th><td>{{ data.user_id }}</td></tr>{% endif %}
+        <tr><th>Test</th><td>{% if data.test_name %}{{ data.test_name }}{% else %}{{ data.test_id | default(value="None") }}{% endif %}</td></tr>
+        <tr><th>Started</th><td>{{ data.start_time }}</td></tr>
+        <tr><th>Last submitted</th><td>{{ data.submit_time }}</td></tr>
+        <tr><th>Furthest page</th><td>{{ data.last_page }}</td></tr>
+        <tr><th>Completed</th><td>{% if data.completed %}Yes{% else %}No{% endif %}</td></tr>
+        <tr><th>Consent withdrawn</th><td>{% if data.consent_withdrawn %}Yes{% else %}No{% endif %}</td></tr>
+    </table>
+    <h2>Answers</h2>
+    <table class="admin-table">
+        <tr><th>Question</th><th>Answer</th><th>Stored as</th></tr>
+        {% for answer in data.answers %}
+            <tr>
+                <td>{{ answer.question }}</td>
+                <td>{% if answer.answer %}{{ answer.answer }}{% else %}Not answered{% endif %}</td>
+                <td><code>{{ answer.stored | json_encode }}</code></td>
+            </tr>
+        {% endfor %}
+    </table>
+    {% if data.scores %}
+        <h2>Scores</h2>
+        <table class="admin-table">
+            <tr><th>Scale</th><th>Mean</th><th>Estimate</th></tr>
+            {% for score in data.scores %}
+                <tr>
+                    <td>{{ score.scale }}</td>
+                    <td>{{ score.mean | round(precision=2) }}</td>
+                    <td>{% if score.theta %}{{ score.theta | round(precision=2) }} &plusmn; {{ score.theta_se | round(precision=2) }}{% endif %}</td>
+                </tr>
+            {% endfor %}
+        </table>
+    {% endif %}
+    {% if data.quality %}
+        <h2>Quality Indices</h2>
+        <pre>{{ data.quality | json_encode(pretty=true) }}</pre>
+    {% endif %}
+    <h2>Timing and Interaction Data</h2>
+    <pre>{{ data.paradata | json_encode(pretty=true) }}</pre>
+    {% if data.withdrawals %}
+        <h2>Withdrawals</h2>
+        <table class="admin-table">
+            <tr><th>Time</th><th>Action</th><th>Via</th></tr>
+            {% for withdrawal in data.withdrawals %}
+                <tr><td>{{ withdrawal.time }}</td><td>{{ withdrawal.action }}</td><td>{{ withdrawal.via }}</td></tr>
+            {% endfor %}
+        </table>
+    {% endif %}
+    <h2>Stored Response</h2>
+    <pre>{{ data.content | json_encode(pretty=true) }}</pre>
+{% endblock content %}
+"#;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct AccessContext<'r> {
+    token: &'r str,
+    #[serde(flatten)]
+    report: AccessReport,
+}
+
+#[get("/access/<id>/<token>")]
+pub async fn access_report(id: &str, token: &str, pool: &State<PgPool>) -> Option<Template> {
+    let response_id: Uuid = id.parse().ok()?;
+    if !access::verify(response_id, token) {
+        return None;
+    }
+    let report = access::report(response_id, &mut pool.acquire().await.unwrap()).await?;
+    Some(Template::render("access.html", &TemplateContext {
+        title: "Your Data",
+        style_hash: &style_hash().await,
+        data: AccessContext { token, report },
+    }))
+}
+
+#[get("/access/<id>/<token>/json")]
+pub async fn access_json(id: &str, token: &str, pool: &State<PgPool>) -> Option<Attachment> {
+    let response_id: Uuid = id.parse().ok()?;
+    if !access::verify(response_id, token) {
+        return None;
+    }
+    let report = access::report(response_id, &mut pool.acquire().await.unwrap()).await?;
+    let body = serde_json::to_vec_pretty(&report).unwrap();
+    Some(Attachment::new(ContentType::JSON, &format!("{}.json", response_id), body))
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use rocket::State;
+use rocket::form::Form;
+use rocket::http::CookieJar;
+use rocket::response::Redirect;
+use rocket::serde::Serialize;
+use rocket_dyn_templates::Template;
+use sqlx::PgPool;
+use crate::accounts::{self, User};
+use crate::database;
+use crate::resume;
+use crate::sharing;
+use crate::tests::get_test;
+use crate::util::format_timestamp;
+use super::{TemplateContext, style_hash};
+
+pub static ACCOUNT_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    {% if data.stage == "login" %}
+        <h1>Log In</h1>
+        <p>Log in to keep the results of the tests you take. No account yet?
+        <a href="/register">Register</a>.</p>
+    {% else %}
+        <h1>Register</h1>
+        <p>With an account, the tests you take are kept together so you can see your results
+        again later. Already have one? <a href="/login">Log in</a>.</p>
+    {% endif %}
+    {% if data.error %}<p class="error">{{ data.error }}</p>{% endif %}
+    <form action="/{{ data.stage }}" method="post">
+        <p><input type="email" name="email" placeholder="Email" value="{{ data.email }}"></p>
+        <p><input type="password" name="password" placeholder="Password"></p>
+        <input type="submit" value="{% if data.stage == "login" %}Log in{% else %}Register{% endif %}">
+    </form>
+{% endblock content %}
+"#;
+
+pub static RESULTS_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>My Results</h1>
+    <p>Logged in as {{ data.email }}.</p>
+    <form action="/logout" method="post"><input type="submit" value="Log out"></form>
+    {% if data.responses %}
+        <table class="admin-table">
+            <tr><th>Test</th><th>Attempt</th><th>Started</th><th></th></tr>
+            {% for response in data.responses %}
+                <tr>
+                    <td>{{ response.test_name }}</td>
+                    <td>{{ response.attempt }}</td>
+                    <td>{{ response.start_time }}</td>
+                    <td>{% if response.completed %}<a href="/feedback/{{ response.test_id }}/{{ response.response_id }}/{{ response.token }}">Feedback</a>{% else %}<a href="/resume/{{ response.response_id }}/{{ response.resume_token }}">Continue</a>{% endif %}</td>
+                </tr>
+            {% endfor %}
+        </table>
+        {% for test in data.tests %}
+            <form action="/test/{{ test.id }}/new" method="post">
+                <input type="submit" value="Start a new attempt at the {{ test.name }}">
+            </form>
+        {% endfor %}
+    {% else %}
+        <p>You haven't taken any tests while logged in yet. <a href="/">Take one</a>.</p>
+    {% endif %}
+{% endblock content %}
+"#;
+
+#[derive(Serialize, Default)]
+#[serde(crate = "rocket::serde")]
+struct AccountContext {
+    stage: &'static str,
+    error: Option<&'static str>,
+    email: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ResultEntry {
+    response_id: String,
+    token: String,
+    resume_token: String,
+    test_id: String,
+    test_name: String,
+    /// The number of the attempt at the test, from 1 for the first.
+    attempt: usize,
+    start_time: String,
+    completed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct TestEntry {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ResultsContext {
+    email: String,
+    responses: Vec<ResultEntry>,
+    /// The tests taken, once each.
+    tests: Vec<TestEntry>,
+}
+
+#[derive(FromForm)]
+pub struct Credentials {
+    email: String,
+    password: String,
+}
+
+async fn render(data: AccountContext) -> Template {
+    let title = if data.stage == "login" { "Log In" } else { "Register" };
+    Template::render("account.html", &TemplateContext {
+        title,
+        style_hash: &style_hash().await,
+        data,
+    })
+}
+
+#[get("/login")]
+pub async fn login_form() -> Template {
+    render(AccountContext { stage: "login", ..AccountContext::default() }).await
+}
+
+#[post("/login", data = "<form>")]
+pub async fn login(form: Form<Credentials>, cookies: &CookieJar<'_>, pool: &State<PgPool>) -> Result<Redirect, Template> {
+    match accounts::authenticate(&form.email, &form.password, &mut pool.acquire().await.unwrap()).await {
+        Some(user_id) => {
+            accounts::log_in(cookies, user_id);
+            Ok(Redirect::to(uri!(my_results)))
+        }
+        None => Err(render(AccountContext {
+            stage: "login",
+            error: Some("Wrong email or password."),
+            email: form.email.clone(),
+        }).await),
+    }
+}
+
+#[get("/register")]
+pub async fn register_form() -> Template {
+    render(AccountContext { stage: "register", ..AccountContext::default() }).await
+}
+
+#[post("/register", data = "<form>")]
+pub async fn register(form: Form<Credentials>, cookies: &CookieJar<'_>, pool: &State<PgPool>) -> Result<Redirect, Template> {
+    match accounts::register(&form.email, &form.password, &mut pool.acquire().await.unwrap()).await {
+        Ok(user_id) => {
+            accounts::log_in(cookies, user_id);
+            Ok(Redirect::to(uri!(my_results)))
+        }
+        Err(error) => Err(render(AccountContext {
+            stage: "register",
+            error: Some(error),
+            email: form.email.clone(),
+        }).await),
+    }
+}
+
+#[post("/logout")]
+pub fn logout(cookies: &CookieJar<'_>) -> Redirect {
+    accounts::log_out(cookies);
+    Redirect::to("/")
+}
+
+#[get("/my-results")]
+pub async fn my_results(user: User, cookies: &CookieJar<'_>, pool: &State<PgPool>) -> Result<Template, Redirect> {
+    let mut conn = pool.acquire().await.unwrap();
+    let email = match database::get_user_email(user.user_id, &mut conn).await {
+        Some(email) => email,
+        None => {
+            accounts::log_out(cookies);
+            return Err(Redirect::to(uri!(login_form)));
+        }
+    };
+    // Responses come newest first, so attempts are numbered from the end.
+    let mut attempts: HashMap<String, usize> = HashMap::new();
+    let mut responses: Vec<ResultEntry> = database::get_user_responses(user.user_id, &mut conn).await.into_iter().rev()
+        .filter_map(|(response_id, test_id, start_time, completed)| {
+            let test = get_test(test_id.as_deref()?)?;
+            let attempt = attempts.entry(test.id.clone()).or_default();
+            *attempt += 1;
+            Some(ResultEntry {
+                response_id: response_id.to_string(),
+                token: sharing::feedback_token(response_id),
+                resume_token: resume::token(response_id),
+                test_id: test.id.clone(),
+                test_name: test.name.clone(),
+                attempt: *attempt,
+                start_time: format_timestamp(start_time),
+                completed,
+            })
+        })
+        .collect();
+    responses.reverse();
+    let mut tests: Vec<TestEntry> = vec![];
+    for response in &responses {
+        if !tests.iter().any(|test| test.id == response.test_id) {
+            tests.push(TestEntry { id: response.test_id.clone(), name: response.test_name.clone() });
+        }
+    }
+    Ok(Template::render("my_results.html", &TemplateContext {
+        title: "My Results",
+        style_hash: &style_hash().await,
+        data: ResultsContext { email, responses, tests },
+    }))
+}
+
+#[get("/my-results", rank = 2)]
+pub fn my_results_login() -> Redirect {
+    Redirect::to(uri!(login_form))
+}
@@ -0,0 +1,487 @@
+use rocket::State;
+use rocket::form::Form;
+use rocket::futures::StreamExt;
+use rocket::futures::future::ready;
+use rocket::http::{ContentType, CookieJar};
+use rocket::response::Redirect;
+use rocket::response::stream::TextStream;
+use rocket::serde::Serialize;
+use rocket_dyn_templates::Template;
+use serde_json::Value;
+use sqlx::PgPool;
+use crate::admin::{self, Admin, AdminConfig};
+use crate::aggregates::{self, Mechanism};
+use crate::codebook::Codebook;
+use crate::database;
+use crate::dataset::{delimited_line, Cell, Dataset, ExportFilter};
+use crate::export::{dta::write_dta, parquet::write_parquet, sav::write_sav};
+use crate::funnel::{funnel, Funnel};
+use crate::quality;
+use crate::retest::{self, RetestReport};
+use crate::tests::{all_tests, Test};
+use crate::util::{format_timestamp, is_iso_date};
+use super::{Attachment, TemplateContext, style_hash};
+
+pub static LOGIN_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Administration</h1>
+    {% if data.error %}<p class="error">{{ data.error }}</p>{% endif %}
+    <form action="/admin/login" method="post">
+        <p><input type="password" name="token" placeholder="Admin token"></p>
+        <input type="submit" value="Log in">
+    </form>
+{% endblock content %}
+"#;
+
+pub static INDEX_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Administration</h1>
+    <table class="admin-table">
+        {% for test in data.tests %}
+            <tr>
+                <th>{{ test.name }}</th>
+                <td><a href="/admin/quality/{{ test.id }}">Quality</a></td>
+                <td><a href="/admin/funnel/{{ test.id }}">Drop-off</a></td>
+                <td><a href="/admin/export/{{ test.id }}/csv">CSV</a></td>
+                <td><a href="/admin/codebook/{{ test.id }}">Codebook</a></td>
+                <td><a href="/admin/aggregates/{{ test.id }}">Aggregates</a></td>
+                <td><a href="/admin/retest/{{ test.id }}">Test-retest</a></td>
+            </tr>
+        {% endfor %}
+    </table>
+{% endblock content %}
+"#;
+
+pub static QUALITY_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Response Quality</h1>
+    <form action="/admin/quality/{{ data.test.id }}" method="post">
+        <input type="submit" value="Recompute">
+    </form>
+    <table class="admin-table">
+        <tr>
+            <th>Response</th><th>Longstring</th><th>IRV</th><th>Synonyms</th><th>Antonyms</th>
+            <th>Mahalanobis</th><th>Seconds/item</th><th>Failed checks</th><th>Flags</th>
+        </tr>
+        {% for row in data.rows %}
+            <tr {% if row.quality.flags | length > 0 %}class="flagged"{% endif %}>
+                <td>{{ row.response_id }}</td>
+                <td>{{ row.quality.longstring }}</td>
+                <td>{% if row.quality.irv %}{{ row.quality.irv | round(precision=2) }}{% endif %}</td>
+                <td>{% if row.quality.synonym_consistency %}{{ row.quality.synonym_consistency | round(precision=2) }}{% endif %}</td>
+                <td>{% if row.quality.antonym_consistency %}{{ row.quality.antonym_consistency | round(precision=2) }}{% endif %}</td>
+                <td>{% if row.quality.mahalanobis %}{{ row.quality.mahalanobis | round(precision=2) }}{% endif %}</td>
+                <td>{% if row.quality.seconds_per_item %}{{ row.quality.seconds_per_item | round(precision=1) }}{% endif %}</td>
+                <td>{{ row.quality.attention_failed }}</td>
+                <td>{{ row.quality.flags | join(sep=", ") }}</td>
+            </tr>
+        {% endfor %}
+    </table>
+{% endblock content %}
+"#;
+
+pub static FUNNEL_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Drop-off: {{ data.test.name }}</h1>
+    <form action="/admin/funnel/{{ data.test.id }}" method="get">
+        From <input type="date" name="from" value="{{ data.from }}">
+        to <input type="date" name="to" value="{{ data.to }}">
+        <input type="submit" value="Update">
+    </form>
+    <p>{{ data.funnel.starts }} started, {{ data.funnel.completions }} completed.</p>
+    <table class="admin-table">
+        <tr>
+            <th>Page</th><th>Reached</th><th>Skipped</th><th>Continued</th><th>Continuation</th>
+        </tr>
+        {% for page in data.funnel.pages %}
+            <tr>
+                <td>{{ page.page + 1 }}. {{ page.title }}{% if page.conditional %} (conditional){% endif %}</td>
+                <td>{{ page.reached }}</td>
+                <td>{% if page.conditional %}{{ page.skipped }} ({{ page.skip_rate * 100 | round }}%){% endif %}</td>
+                <td>{{ page.continued }}</td>
+                <td>{{ page.continuation_rate * 100 | round }}%</td>
+            </tr>
+        {% endfor %}
+    </table>
+{% endblock content %}
+"#;
+
+pub static CODEBOOK_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Codebook: {{ data.title }}</h1>
+    <p>
+        Download as <a href="/admin/codebook/{{ data.test_id }}/md">Markdown</a>
+        or <a href="/admin/codebook/{{ data.test_id }}/xml">DDI-Codebook XML</a>.
+    </p>
+    {% for var in data.variables %}
+        <h2><code>{{ var.name }}</code></h2>
+        <p>{{ var.label }}</p>
+        <ul>
+            <li>Type: {{ var.kind }}</li>
+            {% if var.page %}<li>Page: {{ var.page }}</li>{% endif %}
+            <li>Universe: {{ var.universe }}</li>
+            {% for keying in var.keying %}<li>Scale: {{ keying }}</li>{% endfor %}
+            {% if var.derivation %}<li>Derivation: {{ var.derivation }}</li>{% endif %}
+            <li>Privacy: {{ var.privacy }}</li>
+        </ul>
+        {% if var.codes | length > 0 %}
+            <table class="admin-table">
+                <tr><th>Code</th><th>Label</th></tr>
+                {% for code in var.codes %}
+                    <tr><td>{{ code.value }}</td><td>{{ code.label }}</td></tr>
+                {% endfor %}
+            </table>
+        {% endif %}
+    {% endfor %}
+{% endblock content %}
+"#;
+
+pub static AGGREGATES_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Published Statistics: {{ data.test.name }}</h1>
+    <p>
+        Spent {{ data.spent_epsilon | round(precision=2) }} of {{ data.total_epsilon }} epsilon and
+        {{ data.spent_delta }} of {{ data.total_delta }} delta.
+        <a href="/statistics/{{ data.test.id }}">Public page</a>
+    </p>
+    {% if data.error %}<p class="error">{{ data.error }}</p>{% endif %}
+    <form action="/admin/aggregates/{{ data.test.id }}" method="post">
+        <select name="mechanism">
+            <option value="laplace">Laplace</option>
+            <option value="gaussian">Gaussian</option>
+        </select>
+        &epsilon; <input type="number" name="epsilon" step="any" value="{{ data.default_epsilon }}">
+        &delta; <input type="number" name="delta" step="any" value="{{ data.default_delta }}">
+        <input type="submit" value="Publish">
+    </form>
+    <table class="admin-table">
+        <tr><th>Published</th><th>Mechanism</th><th>Epsilon</th><th>Delta</th></tr>
+        {% for release in data.releases %}
+            <tr>
+                <td>{{ release.created }}</td>
+                <td>{{ release.mechanism }}</td>
+                <td>{{ release.epsilon }}</td>
+                <td>{{ release.delta }}</td>
+            </tr>
+        {% endfor %}
+    </table>
+{% endblock content %}
+"#;
+
+pub static RETEST_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Test-Retest: {{ data.test.name }}</h1>
+    <p>
+        Respondents who took the test more than once: {{ data.report.series }}.
+        {% if data.report.mean_interval_days %}Their first two attempts, which are compared below, were on average
+        {{ data.report.mean_interval_days | round(precision=1) }} days apart.{% endif %}
+    </p>
+    <table class="admin-table">
+        <tr><th>Scale</th><th>Pairs</th><th>Correlation</th><th>Mean change</th></tr>
+        {% for scale in data.report.scales %}
+            <tr>
+                <td>{{ scale.scale }}</td>
+                <td>{{ scale.pairs }}</td>
+                <td>{% if scale.correlation is number %}{{ scale.correlation | round(precision=2) }}{% endif %}</td>
+                <td>{% if scale.mean_change is number %}{{ scale.mean_change | round(precision=2) }}{% endif %}</td>
+            </tr>
+        {% endfor %}
+    </table>
+{% endblock content %}
+"#;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct FunnelContext<'r> {
+    test: &'r Test,
+    from: &'r str,
+    to: &'r str,
+    funnel: Funnel,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct AggregateReleaseRow {
+    created: String,
+    mechanism: String,
+    epsilon: f64,
+    delta: f64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct AggregatesContext<'r> {
+    test: &'r Test,
+    spent_epsilon: f64,
+    spent_delta: f64,
+    total_epsilon: f64,
+    total_delta: f64,
+    default_epsilon: f64,
+    default_delta: f64,
+    error: Option<String>,
+    releases: Vec<AggregateReleaseRow>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct RetestContext<'r> {
+    test: &'r Test,
+    report: RetestReport,
+}
+
+#[derive(FromForm)]
+pub struct PublishForm {
+    mechanism: Mechanism,
+    epsilon: f64,
+    delta: f64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct LoginContext<'r> {
+    error: Option<&'r str>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct IndexContext {
+    tests: Vec<&'static Test>,
+}
+
+#[derive(FromForm)]
+pub struct AdminLogin {
+    token: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct QualityRow {
+    response_id: String,
+    quality: Value,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct QualityContext<'r> {
+    test: &'r Test,
+    rows: Vec<QualityRow>,
+}
+
+#[get("/admin/login")]
+pub async fn login_form() -> Template {
+    Template::render("admin_login.html", &TemplateContext {
+        title: "Administration",
+        style_hash: &style_hash().await,
+        data: LoginContext { error: None },
+    })
+}
+
+#[post("/admin/login", data = "<form>")]
+pub async fn login(form: Form<AdminLogin>, config: &State<AdminConfig>, cookies: &CookieJar<'_>) -> Result<Redirect, Template> {
+    if !config.accepts(&form.token) {
+        return Err(Template::render("admin_login.html", &TemplateContext {
+            title: "Administration",
+            style_hash: &style_hash().await,
+            data: LoginContext { error: Some("That isn't the admin token.") },
+        }));
+    }
+    admin::log_in(cookies, &form.token);
+    Ok(Redirect::to(uri!(index)))
+}
+
+#[get("/admin")]
+pub async fn index(_admin: Admin) -> Template {
+    Template::render("admin_index.html", &TemplateContext {
+        title: "Administration",
+        style_hash: &style_hash().await,
+        data: IndexContext { tests: all_tests() },
+    })
+}
+
+/// Sends browsers without the admin token to the admin login page.
+#[catch(401)]
+pub fn unauthorized() -> Redirect {
+    Redirect::to(uri!(login_form))
+}
+
+#[get("/admin/quality/<test>")]
+pub async fn quality_report(_admin: Admin, test: &Test, pool: &State<PgPool>) -> Template {
+    let rows = database::get_all_qualities(&test.id, &mut pool.acquire().await.unwrap()).await;
+    Template::render("admin_quality.html", &TemplateContext {
+        title: "Admin - Response Quality",
+        style_hash: &style_hash().await,
+        data: QualityContext {
+            test,
+            rows: rows.into_iter()
+                .map(|(response_id, quality)| QualityRow { response_id: response_id.to_string(), quality })
+                .collect(),
+        }
+    })
+}
+
+#[post("/admin/quality/<test>")]
+pub async fn assess_quality(_admin: Admin, test: &Test, pool: &State<PgPool>) -> Redirect {
+    quality::assess_and_store(test, &mut pool.acquire().await.unwrap()).await;
+    Redirect::to(uri!(quality_report(test=test)))
+}
+
+#[get("/admin/funnel/<test>?<from>&<to>")]
+pub async fn funnel_report(_admin: Admin, test: &Test, from: Option<&str>, to: Option<&str>, pool: &State<PgPool>) -> Template {
+    let from = from.filter(|d| is_iso_date(d));
+    let to = to.filter(|d| is_iso_date(d));
+    let progress = database::get_progress(&test.id, from, to, &mut pool.acquire().await.unwrap()).await;
+    Template::render("admin_funnel.html", &TemplateContext {
+        title: "Admin - Drop-off",
+        style_hash: &style_hash().await,
+        data: FunnelContext {
+            test,
+            from: from.unwrap_or(""),
+            to: to.unwrap_or(""),
+            funnel: funnel(test, &progress),
+        }
+    })
+}
+
+#[get("/admin/export/<test>/<format>?<filter..>", rank = 2)]
+pub async fn export<'r>(_admin: Admin, test: &'r Test, format: &str, filter: ExportFilter, pool: &'r State<PgPool>) -> Option<(ContentType, TextStream![String + 'r])> {
+    let (content_type, delimiter) = match format {
+        "csv" => (ContentType::CSV, ','),
+        "tsv" => (ContentType::new("text", "tab-separated-values"), '\t'),
+        _ => return None,
+    };
+    let filter = filter.checked();
+    Some((content_type, TextStream! {
+        let dataset = Dataset::new(test, filter.text);
+        let names: Vec<String> = dataset.columns.iter().map(|c| c.name.clone()).collect();
+        yield delimited_line(&names, delimiter);
+        let mut conn = pool.acquire().await.unwrap();
+        let mut records = database::stream_records(test, &filter, &mut conn);
+        while let Some(record) = records.next().await {
+            if Dataset::keep(&filter, &record) {
+                let cells: Vec<String> = dataset.row(&record).iter().map(|cell| cell.to_text()).collect();
+                yield delimited_line(&cells, delimiter);
+            }
+        }
+    }))
+}
+
+// Reads the filtered responses of a test into memory, for formats that cannot be streamed.
+async fn export_rows<'a>(test: &'a Test, filter: ExportFilter, pool: &PgPool) -> (Dataset<'a>, Vec<Vec<Cell>>) {
+    let filter = filter.checked();
+    let dataset = Dataset::new(test, filter.text);
+    let mut conn = pool.acquire().await.unwrap();
+    let rows = database::stream_records(test, &filter, &mut conn)
+        .filter(|record| ready(Dataset::keep(&filter, record)))
+        .map(|record| dataset.row(&record))
+        .collect().await;
+    (dataset, rows)
+}
+
+#[get("/admin/export/<test>/sav?<filter..>")]
+pub async fn export_sav(_admin: Admin, test: &Test, filter: ExportFilter, pool: &State<PgPool>) -> Attachment {
+    let (dataset, rows) = export_rows(test, filter, pool).await;
+    let body = write_sav(&test.name, &dataset.columns, &rows);
+    Attachment::new(ContentType::Binary, &format!("{}.sav", test.id), body)
+}
+
+#[get("/admin/export/<test>/dta?<filter..>")]
+pub async fn export_dta(_admin: Admin, test: &Test, filter: ExportFilter, pool: &State<PgPool>) -> Attachment {
+    let (dataset, rows) = export_rows(test, filter, pool).await;
+    let body = write_dta(&test.name, &dataset.columns, &rows);
+    Attachment::new(ContentType::Binary, &format!("{}.dta", test.id), body)
+}
+
+// Buffered in memory, as the route has to produce one body; the CLI writes straight to a file.
+#[get("/admin/export/<test>/parquet?<filter..>")]
+pub async fn export_parquet(_admin: Admin, test: &Test, filter: ExportFilter, pool: &State<PgPool>) -> Attachment {
+    let filter = filter.checked();
+    let dataset = Dataset::new(test, filter.text);
+    let mut conn = pool.acquire().await.unwrap();
+    let records = database::stream_records(test, &filter, &mut conn);
+    let body = write_parquet(&dataset, &filter, records, vec![]).await;
+    Attachment::new(ContentType::Binary, &format!("{}.parquet", test.id), body)
+}
+
+#[get("/admin/codebook/<test>")]
+pub async fn codebook(_admin: Admin, test: &Test) -> Template {
+    Template::render("admin_codebook.html", &TemplateContext {
+        title: "Admin - Codebook",
+        style_hash: &style_hash().await,
+        data: Codebook::new(test),
+    })
+}
+
+#[get("/admin/codebook/<test>/md")]
+pub fn codebook_markdown(_admin: Admin, test: &Test) -> Attachment {
+    let body = Codebook::new(test).markdown().into_bytes();
+    Attachment::new(ContentType::new("text", "markdown"), &format!("{}-codebook.md", test.id), body)
+}
+
+#[get("/admin/codebook/<test>/xml")]
+pub fn codebook_ddi(_admin: Admin, test: &Test) -> Attachment {
+    let body = Codebook::new(test).ddi().into_bytes();
+    Attachment::new(ContentType::XML, &format!("{}-codebook.xml", test.id), body)
+}
+
+async fn aggregates_page(test: &Test, error: Option<String>, pool: &PgPool) -> Template {
+    let mut conn = pool.acquire().await.unwrap();
+    let (spent_epsilon, spent_delta) = database::get_spent_budget(&test.id, &mut conn).await;
+    let releases = database::get_aggregate_releases(&test.id, &mut conn).await.into_iter()
+        .map(|(created, mechanism, epsilon, delta, _)| AggregateReleaseRow {
+            created: format_timestamp(created), mechanism, epsilon, delta,
+        })
+        .collect();
+    Template::render("admin_aggregates.html", &TemplateContext {
+        title: "Admin - Published Statistics",
+        style_hash: &style_hash().await,
+        data: AggregatesContext {
+            test,
+            spent_epsilon,
+            spent_delta,
+            total_epsilon: aggregates::TOTAL_EPSILON,
+            total_delta: aggregates::TOTAL_DELTA,
+            default_epsilon: aggregates::DEFAULT_EPSILON,
+            default_delta: aggregates::DEFAULT_DELTA,
+            error,
+            releases,
+        }
+    })
+}
+
+#[get("/admin/aggregates/<test>")]
+pub async fn aggregates_report(_admin: Admin, test: &Test, pool: &State<PgPool>) -> Template {
+    aggregates_page(test, None, pool).await
+}
+
+#[post("/admin/aggregates/<test>", data = "<form>")]
+pub async fn publish_aggregates(_admin: Admin, test: &Test, form: Form<PublishForm>, pool: &State<PgPool>) -> Result<Redirect, Template> {
+    let result = aggregates::publish(test, form.mechanism, form.epsilon, form.delta, &mut pool.acquire().await.unwrap()).await;
+    match result {
+        Ok(()) => Ok(Redirect::to(uri!(aggregates_report(test=test)))),
+        Err(error) => Err(aggregates_page(test, Some(error), pool).await),
+    }
+}
+
+#[get("/admin/retest/<test>")]
+pub async fn retest_report(_admin: Admin, test: &Test, pool: &State<PgPool>) -> Template {
+    let attempts = database::get_retests(&test.id, &mut pool.acquire().await.unwrap()).await;
+    Template::render("admin_retest.html", &TemplateContext {
+        title: "Admin - Test-Retest",
+        style_hash: &style_hash().await,
+        data: RetestContext { test, report: retest::report(test, &attempts) },
+    })
+}
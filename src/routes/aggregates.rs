@@ -0,0 +1,74 @@
+use rocket::State;
+use rocket::serde::Serialize;
+use rocket_dyn_templates::Template;
+use sqlx::PgPool;
+use crate::aggregates::Aggregates;
+use crate::database;
+use crate::tests::Test;
+use crate::util::format_timestamp;
+use super::{TemplateContext, style_hash};
+
+pub static TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Statistics: {{ data.test.name }}</h1>
+    {% if data.release %}
+        <p>Based on the responses of everyone who consented to their data being used in research,
+        as of {{ data.release.created }}. To protect the privacy of respondents, every number
+        includes random noise ({{ data.release.mechanism }} mechanism, &epsilon; = {{ data.release.epsilon }}{% if data.release.delta > 0 %}, &delta; = {{ data.release.delta }}{% endif %}),
+        so small groups in particular are only approximate.</p>
+        {% for group in data.release.aggregates.groups %}
+            <h2>{{ group.label }}</h2>
+            <p>About {{ group.count }} respondents.</p>
+            <table class="aggregate-table">
+                {% for scale in group.scales %}
+                    <tr>
+                        <th>{{ scale.scale }}</th>
+                        <td>{% if scale.mean %}{{ scale.mean | round(precision=2) }}{% else %}Too few respondents{% endif %}</td>
+                        <td class="histogram">
+                            {% for bin in scale.histogram %}<span class="bin" style="height: {{ bin.share * 100 | round }}%" title="{{ bin.from }}-{{ bin.to }}: {{ bin.count }}"></span>{% endfor %}
+                        </td>
+                    </tr>
+                {% endfor %}
+            </table>
+        {% endfor %}
+    {% else %}
+        <p>No statistics have been published for this test yet.</p>
+    {% endif %}
+{% endblock content %}
+"#;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Release {
+    created: String,
+    mechanism: String,
+    epsilon: f64,
+    delta: f64,
+    aggregates: Aggregates,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct AggregatesContext<'r> {
+    test: &'r Test,
+    release: Option<Release>,
+}
+
+#[get("/statistics/<test>")]
+pub async fn statistics(test: &Test, pool: &State<PgPool>) -> Template {
+    let releases = database::get_aggregate_releases(&test.id, &mut pool.acquire().await.unwrap()).await;
+    let release = releases.into_iter().next().map(|(created, mechanism, epsilon, delta, content)| Release {
+        created: format_timestamp(created),
+        mechanism,
+        epsilon,
+        delta,
+        aggregates: serde_json::from_value(content).unwrap(),
+    });
+    Template::render("aggregates.html", &TemplateContext {
+        title: "Statistics",
+        style_hash: &style_hash().await,
+        data: AggregatesContext { test, release },
+    })
+}
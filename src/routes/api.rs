@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use rocket::State;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::json::Json;
+use schemars::JsonSchema;
+use serde_json::{to_value, Value};
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgPool;
+use uuid::Uuid;
+use crate::accounts::User;
+use crate::database;
+use crate::openapi::API_BASE;
+use crate::paradata::{self, ClientInfo};
+use crate::progress::{self, Durations, Progress};
+use crate::resume;
+use crate::sharing;
+use crate::tests::{all_tests, get_test, FeedbackItem, Question, Test};
+use super::test::{next_page, previous_page, save_page, score_feedback, shown_elements, Response};
+
+// Version 1 of the JSON API, for clients other than the website, such as native apps. It follows
+// the same flow as the HTML pages: a client creates a response to a test, gets and answers the
+// pages the API directs it to in turn, and fetches the scored feedback once the response is
+// completed. A response is addressed by its id and the token returned when it was created, which
+// also works in the website's resume links. Errors are objects with an `error` message.
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorBody {
+    error: String,
+}
+
+type ApiError = Custom<Json<ErrorBody>>;
+
+fn error(status: Status, message: &str) -> ApiError {
+    Custom(status, Json(ErrorBody { error: message.into() }))
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ScaleInfo {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TestInfo {
+    id: String,
+    name: String,
+    pages: usize,
+    scales: Vec<ScaleInfo>,
+    /// The median time in seconds respondents take to complete the test, once enough have.
+    estimated_duration: Option<f64>,
+    /// For an other-report variant, the test it describes the target of. Responses to these are
+    /// started from the target's invite link rather than through the API.
+    informant_of: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ResponseInfo {
+    response_id: String,
+    test_id: String,
+    token: String,
+    completed: bool,
+    /// The page to get and answer next, if the response isn't completed.
+    next_page: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PageView<'r> {
+    page: usize,
+    adaptive: bool,
+    elements: Vec<&'r Question>,
+    /// The stored answers to the elements, as the values they were submitted as.
+    #[schemars(with = "HashMap<String, Answer>")]
+    values: HashMap<String, Value>,
+    progress: Progress,
+    previous: Option<usize>,
+}
+
+/// An answer as the HTML form would submit it: a choice as the index of the option, a checkbox as
+/// whether it is checked, and text as a string.
+#[derive(Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum Answer {
+    Index(u64),
+    Checked(bool),
+    Text(String),
+}
+
+/// The answers to a page by question id. The text entered with an "Other" option goes under the
+/// question id followed by `.other`. As on the form, every text area shown must be answered, if
+/// only with an empty string.
+#[derive(Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PageAnswers {
+    answers: HashMap<String, Answer>,
+    #[serde(default)]
+    paradata: HashMap<String, String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ScoreInfo {
+    scale: String,
+    name: String,
+    percentage: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct FeedbackView {
+    test_id: String,
+    feedback: Vec<FeedbackItem>,
+    scores: Vec<ScoreInfo>,
+    /// The respondent's feedback page on the website.
+    feedback_url: String,
+}
+
+fn form_value(answer: &Answer) -> Option<String> {
+    match answer {
+        Answer::Index(index) => Some(index.to_string()),
+        Answer::Checked(true) => Some("on".into()),
+        Answer::Checked(false) => None,
+        Answer::Text(text) => Some(text.clone()),
+    }
+}
+
+// The response the id and token are for, with its test and whether it is completed.
+async fn open_response(id: &str, token: &str, conn: &mut PoolConnection<Postgres>) -> Result<(Uuid, &'static Test, bool), ApiError> {
+    let not_found = || error(Status::NotFound, "There is no response with this id and token.");
+    let response_id: Uuid = id.parse().ok().filter(|response_id| resume::verify(*response_id, token)).ok_or_else(not_found)?;
+    match database::get_attempt(response_id, conn).await {
+        Some((Some(test_id), completed)) => Ok((response_id, get_test(&test_id).ok_or_else(not_found)?, completed)),
+        _ => Err(not_found()),
+    }
+}
+
+fn response_info(test: &Test, response_id: Uuid, completed: bool, next_page: Option<usize>) -> ResponseInfo {
+    ResponseInfo {
+        response_id: response_id.to_string(),
+        test_id: test.id.clone(),
+        token: resume::token(response_id),
+        completed,
+        next_page,
+    }
+}
+
+#[get("/tests")]
+pub fn tests(durations: &State<Durations>) -> Json<Vec<TestInfo>> {
+    Json(all_tests().into_iter().map(|test| TestInfo {
+        id: test.id.clone(),
+        name: test.name.clone(),
+        pages: test.pages.len(),
+        scales: test.scales.iter().map(|scale| ScaleInfo { id: scale.id.clone(), name: scale.name.clone() }).collect(),
+        estimated_duration: durations.of(test),
+        informant_of: test.informant_of.clone(),
+    }).collect())
+}
+
+#[get("/tests/<id>")]
+pub fn test(id: &str) -> Result<Json<&'static Test>, ApiError> {
+    get_test(id).map(Json).ok_or_else(|| error(Status::NotFound, "There is no test with this id."))
+}
+
+#[post("/tests/<id>/responses")]
+pub async fn create_response(id: &str, user: Option<User>, pool: &State<PgPool>) -> Result<Created<Json<ResponseInfo>>, ApiError> {
+    let test = get_test(id).ok_or_else(|| error(Status::NotFound, "There is no test with this id."))?;
+    if test.informant_of.is_some() {
+        return Err(error(Status::Forbidden, "Reports on someone else are started from their invite link."));
+    }
+    let mut conn = pool.acquire().await.unwrap();
+    let response_id = Uuid::new_v4();
+    let resp = database::get_or_create_response(response_id, &test.id, user.map(|user| user.user_id), &mut conn).await;
+    let info = response_info(test, response_id, false, next_page(test, 0, response_id, &resp));
+    let location = format!("{}/responses/{}/{}", API_BASE, info.response_id, info.token);
+    Ok(Created::new(location).body(Json(info)))
+}
+
+#[get("/responses/<id>/<token>")]
+pub async fn response(id: &str, token: &str, pool: &State<PgPool>) -> Result<Json<ResponseInfo>, ApiError> {
+    let mut conn = pool.acquire().await.unwrap();
+    let (response_id, test, completed) = open_response(id, token, &mut conn).await?;
+    // Like the resume link, this continues from the page reached last.
+    let next = match completed {
+        true => None,
+        false => {
+            let last_page = database::get_stored_response(response_id, &mut conn).await.map_or(0, |stored| stored.last_page);
+            let resp = database::get_or_create_response(response_id, &test.id, None, &mut conn).await;
+            next_page(test, last_page, response_id, &resp)
+        }
+    };
+    Ok(Json(response_info(test, response_id, completed, next)))
+}
+
+#[get("/responses/<id>/<token>/pages/<page>")]
+pub async fn page(id: &str, token: &str, page: usize, client: ClientInfo, user: Option<User>, durations: &State<Durations>, pool: &State<PgPool>) -> Result<Json<PageView<'static>>, ApiError> {
+    let mut conn = pool.acquire().await.unwrap();
+    let (response_id, test, completed) = open_response(id, token, &mut conn).await?;
+    if completed {
+        return Err(error(Status::Conflict, "The response is completed."));
+    }
+    let resp = database::get_or_create_response(response_id, &test.id, user.map(|user| user.user_id), &mut conn).await;
+    if page >= test.pages.len() || next_page(test, page, response_id, &resp) != Some(page) {
+        return Err(error(Status::NotFound, "This page isn't shown with the answers so far."));
+    }
+    let elements = shown_elements(test, page, response_id, &to_value(&resp).unwrap()).unwrap();
+    let values = elements.iter()
+        .filter_map(|q| Some(q.form_values(resp.get(&q.id)?)))
+        .flatten()
+        .collect();
+    let mut data = database::get_paradata(response_id, &mut conn).await;
+    paradata::served(&mut data, page, &client);
+    database::set_paradata(response_id, data, &mut conn).await;
+    database::reach_page(response_id, page, &mut conn).await;
+    Ok(Json(PageView {
+        page,
+        adaptive: test.adaptive(page).is_some(),
+        elements,
+        values,
+        progress: progress::progress(test, page, &resp, durations.of(test)),
+        previous: previous_page(test, page, &resp),
+    }))
+}
+
+/// Stores the answers to a page and says which page comes next. The response is completed once
+/// no page is left to show.
+#[post("/responses/<id>/<token>/pages/<page>", data = "<body>")]
+pub async fn post_page(id: &str, token: &str, page: usize, body: Json<PageAnswers>, user: Option<User>, pool: &State<PgPool>) -> Result<Json<ResponseInfo>, ApiError> {
+    let mut conn = pool.acquire().await.unwrap();
+    let (response_id, test, completed) = open_response(id, token, &mut conn).await?;
+    if completed {
+        return Err(error(Status::Conflict, "The response is completed."));
+    }
+    let resp = database::get_or_create_response(response_id, &test.id, None, &mut conn).await;
+    if page >= test.pages.len() || next_page(test, page, response_id, &resp) != Some(page) {
+        return Err(error(Status::NotFound, "This page isn't shown with the answers so far."));
+    }
+    let questions = body.answers.iter()
+        .filter_map(|(key, value)| Some((key.clone(), form_value(value)?)))
+        .collect();
+    let response = Response { questions, paradata: body.paradata.clone() };
+    save_page(test, page, response_id, &response, user, &mut conn).await
+        .map_err(|message| error(Status::UnprocessableEntity, &message))?;
+    let resp = database::get_or_create_response(response_id, &test.id, None, &mut conn).await;
+    let next = if test.adaptive(page).is_some() { page } else { page + 1 };
+    let next = next_page(test, next, response_id, &resp);
+    if next.is_none() {
+        database::complete_response(response_id, &mut conn).await;
+    }
+    Ok(Json(response_info(test, response_id, next.is_none(), next)))
+}
+
+#[get("/responses/<id>/<token>/feedback")]
+pub async fn feedback(id: &str, token: &str, pool: &State<PgPool>) -> Result<Json<FeedbackView>, ApiError> {
+    let mut conn = pool.acquire().await.unwrap();
+    let (response_id, test, completed) = open_response(id, token, &mut conn).await?;
+    if !completed {
+        return Err(error(Status::Conflict, "The response isn't completed yet."));
+    }
+    let res = database::get_response(response_id, &mut conn).await
+        .ok_or_else(|| error(Status::NotFound, "There is no response with this id and token."))?;
+    Ok(Json(FeedbackView {
+        test_id: test.id.clone(),
+        feedback: score_feedback(test, &res),
+        scores: test.scales.iter().map(|scale| ScoreInfo {
+            scale: scale.id.clone(),
+            name: scale.name.clone(),
+            percentage: test.scale_percentage(&scale.id, &res),
+        }).collect(),
+        feedback_url: format!("/feedback/{}/{}/{}", test.id, response_id, sharing::feedback_token(response_id)),
+    }))
+}
+
+#[catch(default)]
+pub fn api_error(status: Status, _req: &Request<'_>) -> ApiError {
+    error(status, status.reason().unwrap_or("Error"))
+}
@@ -1,4 +1,9 @@
+use std::collections::HashMap;
+use rocket::serde::Serialize;
+use rocket::State;
 use rocket_dyn_templates::{Template};
+use crate::progress::{self, Durations};
+use crate::tests::all_tests;
 use super::{TemplateContext, style_hash};
 
 pub static TEMPLATE: &str = r#"
@@ -10,18 +15,29 @@ pub static TEMPLATE: &str = r#"
     Consider taking one of the tests below to get feedback on yourself:</p>
     <ul>
         <li> <a href="/test/tipi/0">Ten-Item Personality Inventory</a>: an ultra-quick personality
-        test developed to be quickly able to guesstimate someone's personality.
+        test developed to be quickly able to guesstimate someone's personality.{% if data.minutes.tipi %}
+        It takes about {{ data.minutes.tipi }} minute{% if data.minutes.tipi != 1 %}s{% endif %}.{% endif %}
     </ul>
     <p>The website is still under construction, so I apologize if it is a bit rough around the
     edges.</p>
 {% endblock content %}
 "#;
 
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct IndexContext {
+    /// The estimated minutes to complete each test, by id, for the tests that have an estimate.
+    minutes: HashMap<String, u64>,
+}
+
 #[get("/")]
-pub async fn index() -> Template {
+pub async fn index(durations: &State<Durations>) -> Template {
+    let minutes = all_tests().into_iter()
+        .filter_map(|test| Some((test.id.clone(), progress::minutes(durations.of(test)?))))
+        .collect();
     Template::render("index.html", &TemplateContext {
         title: "Index",
         style_hash: &style_hash().await,
-        data: (),
+        data: IndexContext { minutes },
     })
 }
@@ -0,0 +1,158 @@
+use rocket::State;
+use rocket::serde::Serialize;
+use rocket::serde::json::Json;
+use rocket_dyn_templates::Template;
+use serde_json::Value;
+use crate::openapi::Document;
+use super::{TemplateContext, style_hash};
+
+pub static TEMPLATE: &str = r##"
+{% extends "base" %}
+
+{% block content %}
+    <h1>{{ data.title }}</h1>
+    <p>The JSON API is served under <code>{{ data.base }}</code>. Responses are addressed by the
+    id and token returned when they are started, and errors are objects with an <code>error</code>
+    message. This page shows the <a href="/openapi.json">OpenAPI document</a>, which is generated
+    from the server's code.</p>
+    {% for operation in data.operations %}
+        <div>
+            <h2><code>{{ operation.method | upper }} {{ data.base }}{{ operation.path }}</code></h2>
+            {% if operation.summary %}<p>{{ operation.summary }}</p>{% endif %}
+            {% if operation.parameters %}
+                <p>Parameters:
+                {% for parameter in operation.parameters %}
+                    <code>{{ parameter.name }}</code> ({{ parameter.kind }}){% if not loop.last %},{% endif %}
+                {% endfor %}
+                </p>
+            {% endif %}
+            {% if operation.request %}
+                <p>Request body:
+                {% if operation.request.link %}<a href="#{{ operation.request.link }}">{{ operation.request.name }}</a>{% else %}{{ operation.request.name }}{% endif %}
+                </p>
+            {% endif %}
+            <table class="admin-table">
+                <tr><th>Status</th><th>Description</th><th>Body</th></tr>
+                {% for response in operation.responses %}
+                    <tr>
+                        <td>{{ response.status }}</td>
+                        <td>{{ response.description }}</td>
+                        <td>{% if response.body %}{% if response.body.link %}<a href="#{{ response.body.link }}">{{ response.body.name }}</a>{% else %}{{ response.body.name }}{% endif %}{% endif %}</td>
+                    </tr>
+                {% endfor %}
+            </table>
+        </div>
+    {% endfor %}
+    <h2>Schemas</h2>
+    {% for schema in data.schemas %}
+        <h3 id="{{ schema.name }}">{{ schema.name }}</h3>
+        <pre>{{ schema.schema | json_encode(pretty=true) }}</pre>
+    {% endfor %}
+{% endblock content %}
+"##;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SchemaLabel {
+    name: String,
+    /// The schema among the document's schemas that the label refers to, if any.
+    link: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ParameterView<'r> {
+    name: &'r str,
+    kind: &'r str,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ResponseView<'r> {
+    status: &'r str,
+    description: &'r str,
+    body: Option<SchemaLabel>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct OperationView<'r> {
+    method: &'r str,
+    path: &'r str,
+    summary: Option<&'r str>,
+    parameters: Vec<ParameterView<'r>>,
+    request: Option<SchemaLabel>,
+    responses: Vec<ResponseView<'r>>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SchemaView<'r> {
+    name: &'r str,
+    schema: &'r Value,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct OpenApiContext<'r> {
+    title: &'r str,
+    base: &'r str,
+    operations: Vec<OperationView<'r>>,
+    schemas: Vec<SchemaView<'r>>,
+}
+
+fn label(schema: &Value) -> SchemaLabel {
+    let reference = |schema: &Value| schema["$ref"].as_str().and_then(|reference| reference.rsplit('/').next()).map(String::from);
+    match (reference(schema), reference(&schema["items"])) {
+        (Some(name), _) => SchemaLabel { link: Some(name.clone()), name },
+        (None, Some(name)) => SchemaLabel { name: format!("array of {}", name), link: Some(name) },
+        (None, None) => SchemaLabel { name: schema["type"].as_str().unwrap_or("any").into(), link: None },
+    }
+}
+
+fn body_label(body: &Value) -> Option<SchemaLabel> {
+    body["content"]["application/json"].get("schema").map(label)
+}
+
+#[get("/openapi.json")]
+pub fn openapi_json(document: &State<Document>) -> Json<&Value> {
+    Json(&document.0)
+}
+
+#[get("/openapi")]
+pub async fn openapi(document: &State<Document>) -> Template {
+    let document = &document.0;
+    let mut operations = vec![];
+    for (path, item) in document["paths"].as_object().unwrap() {
+        for (method, operation) in item.as_object().unwrap() {
+            operations.push(OperationView {
+                method,
+                path,
+                summary: operation["summary"].as_str(),
+                parameters: operation["parameters"].as_array().unwrap().iter().map(|parameter| ParameterView {
+                    name: parameter["name"].as_str().unwrap(),
+                    kind: parameter["schema"]["type"].as_str().unwrap(),
+                }).collect(),
+                request: body_label(&operation["requestBody"]),
+                responses: operation["responses"].as_object().unwrap().iter().map(|(status, response)| ResponseView {
+                    status,
+                    description: response["description"].as_str().unwrap(),
+                    body: body_label(response),
+                }).collect(),
+            });
+        }
+    }
+    let schemas = document["components"]["schemas"].as_object().unwrap().iter()
+        .map(|(name, schema)| SchemaView { name, schema })
+        .collect();
+    Template::render("openapi.html", &TemplateContext {
+        title: "API",
+        style_hash: &style_hash().await,
+        data: OpenApiContext {
+            title: document["info"]["title"].as_str().unwrap(),
+            base: document["servers"][0]["url"].as_str().unwrap(),
+            operations,
+            schemas,
+        },
+    })
+}
@@ -1,15 +1,30 @@
 use rocket::serde::Serialize;
 use rocket::form::Form;
 use rocket_dyn_templates::Template;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
 use sqlx::postgres::PgPool;
 use std::collections::HashMap;
 use rocket::State;
-use rocket::http::{CookieJar, Cookie};
+use rocket::http::{CookieJar, Cookie, Status};
 use rocket::response::Redirect;
-use serde_json::Value;
+use rocket::response::status::Custom;
+use serde_json::{to_value, Value};
 use uuid::Uuid;
 use crate::tests::*;
+use crate::cat::{self, Step};
+use crate::irt::eap;
+use crate::paradata::{self, ClientInfo};
 use crate::database;
+use crate::withdrawal;
+use crate::access;
+use crate::accounts::User;
+use crate::retest::{self, Change};
+use crate::informant::{self, InformantFeedback};
+use crate::compatibility::{self, CompatibilitySection, Comparison};
+use crate::sharing::{self, ShareLink};
+use crate::resume;
+use crate::progress::{self, Durations, Progress};
 use super::{TemplateContext, style_hash};
 
 pub static TEST_TEMPLATE: &str = r#"
@@ -17,44 +32,57 @@ pub static TEST_TEMPLATE: &str = r#"
 
 {% block content %}
     {% set N_PAGES = data.test.pages | length %}
+    <div class="progress">
+        <div class="bar">
+            <div class="bar-fill" style="width: {{ data.progress.percentage }}%"></div>
+            <div class="bar-empty" style="width: {{ 100 - data.progress.percentage }}%"></div>
+        </div>
+        <p>Page {{ data.progress.page }} of {{ data.progress.pages }}{% if data.progress.minutes_left %},
+        about {{ data.progress.minutes_left }} minute{% if data.progress.minutes_left != 1 %}s{% endif %} left{% endif %}</p>
+    </div>
 	<form action=
-	    {% if data.page + 1 < N_PAGES %}
-	        "/test/{{data.test.id}}/{{data.page+1}}"
+	    {% if data.adaptive %}
+	        "/test/{{data.test.id}}/{{data.response_id}}/{{data.token}}/{{data.page}}/cat"
+	    {% elif data.page + 1 < N_PAGES %}
+	        "/test/{{data.test.id}}/{{data.response_id}}/{{data.token}}/{{data.page+1}}"
 	    {% else %}
-	        "/feedback/{{data.test.id}}"
+	        "/test/{{data.test.id}}/{{data.response_id}}/{{data.token}}/{{data.page}}/finish"
 	    {% endif %} method="post">
-		{% for element in data.test.pages[data.page].elements %}
+		{% for element in data.elements %}
 		    {% if element.content.AlignText %}
 		        {% set content = element.content.AlignText %}
                 <div class="mc-align">{{ content.text }}</div>
 		    {% elif element.content.McQuestion %}
 		        {% set content = element.content.McQuestion %}
+                {% set value = data.values[element.id] | default(value="") %}
                 <div class="mc-question-options">
                     {% for opt in content.options %}
                         <div class="question-option">
                             <input type="radio" id="{{ element.id }}_{{ loop.index-1 }}"
-                                name="questions.{{ element.id }}" value="{{ loop.index-1 }}">
+                                name="questions.{{ element.id }}" value="{{ loop.index-1 }}"{% if value == loop.index-1 %} checked{% endif %}>
                             <label for="{{ element.id }}_{{ loop.index-1 }}">{{ opt }}</label>
                         </div>
                     {% endfor %}
                 </div>
             {% elif element.content.McQuestionVert %}
 		        {% set content = element.content.McQuestionVert %}
+                {% set value = data.values[element.id] | default(value="") %}
+                {% set other_field = element.id ~ ".other" %}
                 <div class="mc-question-vert">
                     <div class="question-options">
                         {% for opt in content.options %}
                             <div class="question-option">
                                 <input type="radio" id="{{ element.id }}_{{ loop.index-1 }}"
-                                    name="questions.{{ element.id }}" value="{{ loop.index-1 }}">
+                                    name="questions.{{ element.id }}" value="{{ loop.index-1 }}"{% if value == loop.index-1 %} checked{% endif %}>
                                 <label for="{{ element.id }}_{{ loop.index-1 }}">{{ opt }}</label>
                             </div>
                         {% endfor %}
                         {% if content.other %}
                             <div class="question-option">
                                 <input type="radio" id="{{ element.id }}_{{ content.options | length }}"
-                                    name="questions.{{ element.id }}" value="{{ content.options | length }}">
+                                    name="questions.{{ element.id }}" value="{{ content.options | length }}"{% if value == content.options | length %} checked{% endif %}>
                                 <label for="{{ element.id }}_{{ content.options | length }}">Other: </label>
-                                <input type="text" name="questions.{{ element.id }}.other"/>
+                                <input type="text" name="questions.{{ element.id }}.other" value="{{ data.values[other_field] | default(value="") }}"/>
                             </div>
                         {% endif %}
                     </div>
@@ -79,25 +107,43 @@ pub static TEST_TEMPLATE: &str = r#"
 		        {% set content = element.content.CheckboxQuestion %}
 		        <div class="cb-question">
                     <label for="{{ element.id }}">
-                        <input type="checkbox" id="{{ element.id }}" name="questions.{{ element.id }}">
+                        <input type="checkbox" id="{{ element.id }}" name="questions.{{ element.id }}"{% if data.values[element.id] | default(value=false) %} checked{% endif %}>
                         {{ content.text }}
 		            </label>
 		        </div>
 		    {% elif element.content == "TextAreaQuestion" %}
 		        {% set content = element.content %}
 		        <div class="text-area-question">
-                    <textarea name="questions.{{ element.id }}" rows=5 cols=50></textarea>
+                    <textarea name="questions.{{ element.id }}" rows=5 cols=50>{{ data.values[element.id] | default(value="") }}</textarea>
                 </div>
             {% else %}
                 {{ element.content | json_encode }}
             {% endif %}
 		{% endfor %}
-		{% if data.page + 1 < N_PAGES %}
+		<input type="hidden" name="paradata.viewport" id="paradata-viewport">
+		<script>
+		    document.getElementById("paradata-viewport").value =
+		        window.innerWidth < 600 ? "small" : window.innerWidth < 1200 ? "medium" : "large";
+		</script>
+		{% if data.adaptive %}
+		    <input class="submit" type="submit" value="Next">
+		{% elif data.page + 1 < N_PAGES %}
 		    <input class="submit" type="submit" value="Next Page">
 		{% else %}
 		    <input class="submit" type="submit" value="Get Results!">
 		{% endif %}
+		{% if data.previous is number and not data.adaptive %}
+		    <input class="previous" type="submit" formaction="/test/{{data.test.id}}/{{data.response_id}}/{{data.token}}/{{data.page}}/back" value="Previous Page">
+		{% endif %}
+	</form>
+	<p class="resume">To continue on another device, open
+	<a href="/resume/{{ data.response_id }}/{{ data.token }}">this link</a> there. Anyone
+	who has the link can see and change your answers.</p>
+	{% if not data.test.informant_of %}
+	<form class="new-attempt" action="/test/{{data.test.id}}/new" method="post">
+	    <input type="submit" value="Start over with a new attempt">
 	</form>
+	{% endif %}
 {% endblock content %}
 "#;
 
@@ -124,6 +170,193 @@ pub static FEEDBACK_TEMPLATE: &str = r#"
             </div>
         {% endif %}
     {% endfor %}
+    {% if data.changes %}
+        <h2>Your Scores Over Time</h2>
+        <table class="change-table">
+            {% for change in data.changes %}
+                <tr>
+                    <th>{{ change.scale }}</th>
+                    <td class="change-chart">
+                        {% for point in change.points %}<span class="point{% if point.current %} current{% endif %}" style="height: {{ point.percentage | round }}%" title="{{ point.date }}: {{ point.percentage | round }}%"></span>{% endfor %}
+                    </td>
+                    <td>{% if change.change is number %}{% if change.change > 0 %}+{% endif %}{{ change.change | round }} points since last time{% endif %}</td>
+                </tr>
+            {% endfor %}
+        </table>
+    {% endif %}
+    {% if data.informant %}
+        <div class="informant">
+            <h2>How Others See You</h2>
+            {% if data.informant.comparison %}
+                <p>Based on {{ data.informant.reports }} people who know you describing you. To keep each
+                of them anonymous, new descriptions are only added {{ data.informant.min_reports }} at a time.</p>
+                <table class="comparison-table">
+                    <tr><th></th><th>You</th><th>Others</th></tr>
+                    {% for row in data.informant.comparison %}
+                        <tr>
+                            <th>{{ row.scale }}</th>
+                            <td>{% if row.own is number %}{{ row.own | round }}%{% endif %}</td>
+                            <td>{% if row.others is number %}{{ row.others | round }}%{% endif %}</td>
+                        </tr>
+                    {% endfor %}
+                </table>
+            {% else %}
+                <p>Find out how others see you: once at least {{ data.informant.min_reports }} people who
+                know you have described you, you will see their average next to your own scores here.
+                So far {{ data.informant.reports }} have.</p>
+            {% endif %}
+            <p>Send them this link: <a href="/informant/{{ data.response_id }}/{{ data.informant.signature }}">/informant/{{ data.response_id }}/{{ data.informant.signature }}</a></p>
+        </div>
+    {% endif %}
+    {% if data.retest_code %}
+    <div class="retest">
+        {% if not data.changes %}
+            <form action="/feedback/{{ data.test_id }}/{{ data.response_id }}/{{ data.token }}/retest" method="post">
+                <p>Taken this test before? Enter the retest code from your earlier feedback to see how
+                your scores have changed.</p>
+                {% if data.unknown_code %}<p class="error">None of your earlier responses to this test has this retest code.
+                Enter it in the browser you took the test in before, or log in to the account you took it with.</p>{% endif %}
+                <input type="text" name="code" placeholder="XXXX-XXXX-XXXX-XXXX-XXXX">
+                <input type="submit" value="Compare">
+            </form>
+        {% endif %}
+        <p>Your retest code is <code>{{ data.retest_code }}</code>. If you take this test again,
+        enter it on your new feedback page to compare your results.</p>
+    </div>
+    {% endif %}
+    {% if data.compatibility %}
+        <div class="compatibility">
+            <h2>Compare With Someone</h2>
+            <p>To compare your results with someone who has also taken this test, exchange
+            compatibility codes with them. Yours is <code>{{ data.compatibility.code }}</code>. Once you
+            have both entered each other's code, you can both see the comparison.</p>
+            {% for partner in data.compatibility.partners %}
+                <p>{% if partner.mutual %}<a href="/feedback/{{ data.test_id }}/{{ data.response_id }}/{{ data.token }}/compare/{{ partner.response_id }}">See comparison {{ loop.index }}</a>{% else %}Comparison {{ loop.index }}: waiting for the other person to enter your code.{% endif %}</p>
+            {% endfor %}
+            {% if data.compatibility.unreturned > 0 %}
+                <p>{{ data.compatibility.unreturned }} {% if data.compatibility.unreturned == 1 %}person has{% else %}people have{% endif %}
+                entered your code. Enter theirs to see the comparison.</p>
+            {% endif %}
+            <form action="/feedback/{{ data.test_id }}/{{ data.response_id }}/{{ data.token }}/compare" method="post">
+                {% if data.unknown_partner %}<p class="error">Nobody else who completed this test has this compatibility code.</p>{% endif %}
+                <input type="text" name="code" placeholder="XXXX-XXXX-XXXX-XXXX-XXXX">
+                <input type="submit" value="Enter code">
+            </form>
+        </div>
+    {% endif %}
+    {% if data.shares is iterable %}
+        <div class="sharing">
+            <h2>Share Your Results</h2>
+            <p>Keep the address of this page to yourself: anyone who has it can see everything on it.
+            To show your results to others, create a share link instead, which you can revoke at any time.</p>
+            {% for share in data.shares %}
+                <form class="share" action="/feedback/{{ data.test_id }}/{{ data.response_id }}/{{ data.token }}/share/{{ share.share_id }}/revoke" method="post">
+                    <a href="/shared/{{ share.share_id }}/{{ share.signature }}">{% if share.summary %}Scores only{% else %}Full results{% endif %}</a>,
+                    {% if share.expires %}expires {{ share.expires }}{% else %}does not expire{% endif %}
+                    <input type="submit" value="Revoke">
+                </form>
+            {% endfor %}
+            <form action="/feedback/{{ data.test_id }}/{{ data.response_id }}/{{ data.token }}/share" method="post">
+                <select name="summary">
+                    <option value="false">Full results</option>
+                    <option value="true">Scores only</option>
+                </select>
+                <select name="expires_days">
+                    <option value="">Does not expire</option>
+                    <option value="1">Expires after a day</option>
+                    <option value="7">Expires after a week</option>
+                    <option value="30">Expires after a month</option>
+                </select>
+                <input type="submit" value="Create link">
+            </form>
+        </div>
+    {% endif %}
+    <div class="withdrawal">
+        <p>If you change your mind about your response being used, you can
+        <a href="/withdraw/{{ data.response_id }}/{{ data.withdrawal_signature }}">withdraw it</a>
+        at any time. Keep this link or write down your withdrawal code,
+        <code>{{ data.withdrawal_code }}</code>, and enter it at <a href="/withdraw">/withdraw</a>.</p>
+        <p>You can also <a href="/access/{{ data.response_id }}/{{ data.access_token }}">see all data we hold about your response</a>.</p>
+    </div>
+{% endblock content %}
+"#;
+
+pub static COMPARE_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Your Results Compared</h1>
+    <p>
+        {% if data.comparison.mean_difference is number %}On average, your scores are
+        {{ data.comparison.mean_difference | round }} points apart.{% endif %}
+        {% if data.comparison.similarity is number %}The shapes of your profiles correlate at
+        {{ data.comparison.similarity | round(precision=2) }}, where 1 means the same pattern of high
+        and low scores and -1 the opposite.{% endif %}
+    </p>
+    {% for item in data.comparison.items %}
+        {% if item.own.Title %}
+            <h2>{{ item.own.Title.text }}</h2>
+        {% elif item.own.Paragraph %}
+            <p>{{ item.own.Paragraph.text }}</p>
+        {% elif item.own.Bar %}
+            {% set own = 100.0 * (item.own.Bar.score - item.own.Bar.min) / (item.own.Bar.max - item.own.Bar.min) %}
+            {% set other = 100.0 * (item.other.Bar.score - item.other.Bar.min) / (item.other.Bar.max - item.other.Bar.min) %}
+            <div class="bar-container">
+                <div class="bar-percent">You: {{ own | round }}%</div>
+                <div class="bar">
+                    <div class="bar-fill" style="width: {{ own }}%"></div>
+                    <div class="bar-empty" style="width: {{ 100 - own }}%"></div>
+                </div>
+            </div>
+            <div class="bar-container">
+                <div class="bar-percent">Them: {{ other | round }}%</div>
+                <div class="bar">
+                    <div class="bar-fill other" style="width: {{ other }}%"></div>
+                    <div class="bar-empty" style="width: {{ 100 - other }}%"></div>
+                </div>
+            </div>
+            <p>{{ item.difference | round }} points apart.</p>
+        {% endif %}
+    {% endfor %}
+    <p><a href="/feedback/{{ data.test_id }}/{{ data.response_id }}/{{ data.token }}">Back to your feedback</a></p>
+{% endblock content %}
+"#;
+
+pub static INFORMANT_DONE_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Thank You</h1>
+    <p>You have already described this person. They will see the average of everyone's
+    descriptions once enough people have described them, never yours alone.</p>
+    <p><a href="/">Take a test yourself</a></p>
+{% endblock content %}
+"#;
+
+pub static SHARED_TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>{{ data.test_name }} Results</h1>
+    <p>Someone has shared {% if data.summary %}their scores{% else %}their results{% endif %} on this test with you.</p>
+    {% for element in data.feedback %}
+        {% if element.Title %}
+            <h2>{{ element.Title.text }}</h2>
+        {% elif element.Paragraph %}
+            <p>{{ element.Paragraph.text }}</p>
+        {% elif element.Bar %}
+            {% set content = element.Bar %}
+            {% set percentage = 100.0 * (content.score - content.min) / (content.max - content.min) %}
+            <div class="bar-container">
+                <div class="bar-percent">{{ percentage | round }}%</div>
+                <div class="bar">
+                    <div class="bar-fill" style="width: {{ percentage }}%"></div>
+                    <div class="bar-empty" style="width: {{ 100 - percentage }}%"></div>
+                </div>
+            </div>
+        {% endif %}
+    {% endfor %}
+    <p><a href="/">Take a test yourself</a></p>
 {% endblock content %}
 "#;
 
@@ -132,97 +365,580 @@ pub static FEEDBACK_TEMPLATE: &str = r#"
 struct TestContext<'r> {
     test: &'r Test,
     page: usize,
+    elements: Vec<&'r Question>,
+    /// The stored answers to the elements, as form values by field name.
+    values: HashMap<String, Value>,
+    adaptive: bool,
+    progress: Progress,
+    previous: Option<usize>,
+    response_id: String,
+    /// Signs the response id in the URLs of the attempt's pages.
+    token: String,
 }
 
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
 struct FeedbackContext<'r> {
     feedback: &'r Vec<FeedbackItem>,
+    test_id: &'r str,
+    response_id: String,
+    token: String,
+    changes: Vec<Change>,
+    retest_code: Option<String>,
+    unknown_code: bool,
+    informant: Option<InformantFeedback>,
+    compatibility: Option<CompatibilitySection>,
+    unknown_partner: bool,
+    shares: Option<Vec<ShareLink>>,
+    withdrawal_code: String,
+    withdrawal_signature: String,
+    access_token: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct CompareContext<'r> {
+    test_id: &'r str,
+    response_id: &'r str,
+    token: &'r str,
+    comparison: Comparison,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SharedContext<'r> {
+    test_name: &'r str,
+    summary: bool,
+    feedback: Vec<FeedbackItem>,
 }
 
 #[derive(FromForm)]
 #[derive(Debug)]
 pub struct Response {
-    questions: HashMap<String, String>
+    pub(super) questions: HashMap<String, String>,
+    pub(super) paradata: HashMap<String, String>,
 }
 
-fn get_resp_map(test: &Test, page: usize, response: &Response) -> HashMap<String, Value> {
+async fn record_submitted(response_id: Uuid, page: usize, response: &Response, conn: &mut PoolConnection<Postgres>) {
+    let mut data = database::get_paradata(response_id, conn).await;
+    paradata::submitted(&mut data, page, &response.paradata);
+    database::set_paradata(response_id, data, conn).await;
+}
+
+fn get_resp_map(shown: &[&Question], response: &Response) -> Result<HashMap<String, Value>, String> {
     let mut resp_map = HashMap::new();
-    let page = &test.pages[page];
-    for question in &page.elements {
-        if let Some(value) = question.convert(&response.questions) {
+    for question in shown {
+        if let Some(value) = question.convert(&response.questions)? {
             resp_map.insert(question.id.clone(), value);
         }
     }
-    resp_map
+    Ok(resp_map)
 }
 
-#[post("/test/<test>/<page>", data="<response>")]
-pub async fn post_test(test: &Test, page: usize, cookies: &CookieJar<'_>, response: Form<Response>, pool: &State<PgPool>) -> Redirect {
-    let resp_id_cookie_name = format!("responseId[{}]", test.id);
-    let response_id = cookies.get(&resp_id_cookie_name).unwrap().value().parse().unwrap();
-    println!("{:?}", &response.questions);
-    let resp_map = get_resp_map(test, page-1, &response);
-    database::update_response(response_id, resp_map, &mut pool.acquire().await.unwrap()).await;
-    Redirect::to(uri!(test(test=test, page=page)))
+// Stores the answers submitted on a page, or says why they can't be stored. Only the questions
+// shown on the page are read, which on an adaptive page is the one item administered. The HTML
+// forms and the API both submit pages through here.
+pub(super) async fn save_page(test: &Test, page: usize, response_id: Uuid, response: &Response, user: Option<User>, conn: &mut PoolConnection<Postgres>) -> Result<(), String> {
+    if page >= test.pages.len() {
+        return Err(format!("The test has no page {}", page));
+    }
+    let mut resp = database::get_or_create_response(response_id, &test.id, user.map(|user| user.user_id), conn).await;
+    let shown = shown_elements(test, page, response_id, &to_value(&resp).unwrap()).unwrap_or_default();
+    let mut resp_map = get_resp_map(&shown, response)?;
+    if let Some((adaptive, items)) = test.adaptive(page) {
+        let scale = test.scale(&adaptive.scale).unwrap();
+        if let Some(item) = scale.items.iter().find(|item| resp_map.contains_key(&item.id)) {
+            resp.extend(resp_map.clone());
+            let resp = to_value(&resp).unwrap();
+            let estimate = eap(items, &scale.keyed_answers(&resp));
+            resp_map.insert("cat".into(), cat::record(&resp, &scale.id, &item.id, estimate));
+        }
+    }
+    database::update_response(response_id, &test.id, resp_map, conn).await;
+    record_submitted(response_id, page, response, conn).await;
+    Ok(())
+}
+
+// Moves each attention check, together with its label, in front of a randomly chosen item. The
+// choice is derived from the response id so that the page looks the same when reloaded.
+fn place_attention_checks(elements: &[Question], response_id: Uuid) -> Vec<&Question> {
+    let mut blocks: Vec<Vec<&Question>> = vec![];
+    let mut iter = elements.iter();
+    while let Some(q) = iter.next() {
+        match (&q.content, iter.clone().next()) {
+            (QuestionContent::AlignText { .. }, Some(_)) => blocks.push(vec![q, iter.next().unwrap()]),
+            _ => blocks.push(vec![q]),
+        }
+    }
+    let (checks, mut blocks): (Vec<_>, Vec<_>) = blocks.into_iter()
+        .partition(|block| block.iter().any(|q| q.is_attention_check()));
+    let mut seed = response_id.as_u128();
+    for check in checks {
+        let items: Vec<usize> = (0..blocks.len())
+            .filter(|i| blocks[*i].iter().any(|q| matches!(q.content, QuestionContent::McQuestion { .. })))
+            .collect();
+        let pos = if items.is_empty() { blocks.len() } else { items[(seed % items.len() as u128) as usize] };
+        seed /= items.len().max(1) as u128;
+        blocks.insert(pos, check);
+    }
+    blocks.into_iter().flatten().collect()
+}
+
+// The elements to show on a page, or `None` if the page is adaptive and has stopped presenting
+// items. An adaptive page shows its headers and paragraphs, plus the chosen item and its label.
+pub(super) fn shown_elements<'a>(test: &'a Test, page: usize, response_id: Uuid, resp: &Value) -> Option<Vec<&'a Question>> {
+    let elements = &test.pages.get(page)?.elements;
+    let (adaptive, items) = match test.adaptive(page) {
+        None => return Some(place_attention_checks(elements, response_id)),
+        Some(adaptive) => adaptive,
+    };
+    let answers = test.scale(&adaptive.scale).unwrap().keyed_answers(resp);
+    match adaptive.next(items, &answers) {
+        Step::Done(_) => None,
+        Step::Administer(i) => {
+            let pos = elements.iter().position(|q| q.id == items[i].id)?;
+            Some(elements.iter().enumerate().filter(|(j, q)| {
+                use QuestionContent::*;
+                match q.content {
+                    Header { .. } | Paragraph { .. } => true,
+                    AlignText { .. } => *j + 1 == pos,
+                    _ => *j == pos,
+                }
+            }).map(|(_, q)| q).collect())
+        }
+    }
+}
+
+// The first page from `page` on that is shown with the answers so far, if any. A page whose
+// condition depends on a question that wasn't answered isn't shown.
+pub(super) fn next_page(test: &Test, page: usize, response_id: Uuid, resp: &HashMap<String, Value>) -> Option<usize> {
+    let content = to_value(resp).unwrap();
+    (page..test.pages.len()).find(|p| {
+        test.pages[*p].condition.holds(resp) == Some(true) && shown_elements(test, *p, response_id, &content).is_some()
+    })
+}
+
+// The nearest earlier page shown with the answers so far, if any. Adaptive pages are skipped,
+// since their items are only presented once.
+pub(super) fn previous_page(test: &Test, page: usize, resp: &HashMap<String, Value>) -> Option<usize> {
+    (0..page).rev().find(|p| test.adaptive(*p).is_none() && test.pages[*p].condition.holds(resp) == Some(true))
+}
+
+fn feedback_redirect(test: &Test, response_id: Uuid) -> Redirect {
+    Redirect::to(uri!(get_feedback(test=test, id=response_id.to_string(), token=sharing::feedback_token(response_id), unknown_code=_, unknown_partner=_)))
+}
+
+// The attempt a test page's URL is for. A link that isn't valid for the test, or is for an attempt
+// that has been removed since, starts over, and a finished attempt goes to its feedback instead.
+async fn open_attempt(test: &Test, id: &str, token: &str, conn: &mut PoolConnection<Postgres>) -> Result<Uuid, Redirect> {
+    let response_id = match id.parse().ok().filter(|response_id| resume::verify(*response_id, token)) {
+        Some(response_id) => response_id,
+        None => return Err(Redirect::to(uri!(start(test=test, page=0)))),
+    };
+    match database::get_attempt(response_id, conn).await {
+        Some((Some(test_id), false)) if test_id == test.id => Ok(response_id),
+        Some((Some(test_id), true)) if test_id == test.id => Err(feedback_redirect(test, response_id)),
+        _ => Err(Redirect::to(uri!(start(test=test, page=0)))),
+    }
+}
+
+fn attempt_page(test: &Test, response_id: Uuid, page: usize) -> Redirect {
+    Redirect::to(uri!(test(test=test, id=response_id.to_string(), token=resume::token(response_id), page=page)))
+}
+
+// The HTML forms only submit answers that pass validation, so answers that don't were sent some
+// other way, and get the reason rather than the page again.
+fn invalid_page(message: String) -> Custom<String> {
+    Custom(Status::UnprocessableEntity, message)
+}
+
+async fn start_attempt(test: &Test, cookies: &CookieJar<'_>, user: Option<User>, conn: &mut PoolConnection<Postgres>) -> Uuid {
+    let response_id = Uuid::new_v4();
+    database::get_or_create_response(response_id, &test.id, user.map(|user| user.user_id), conn).await;
+    cookies.add(Cookie::new(format!("responseId[{}]", test.id), response_id.to_string()));
+    response_id
 }
 
+// Continues the attempt in progress in this browser, or starts one if there is none, including
+// when the cookie is for an attempt that was finished or removed since. The cookie only remembers
+// the attempt for this; the pages of an attempt work without it. Reports on someone else are only
+// started from their invite link, since a report without a target never reaches anyone.
 #[get("/test/<test>/<page>")]
-pub async fn test(test: &Test, page: usize, cookies: &CookieJar<'_>, pool: &State<PgPool>) -> Result<Template, Redirect> {
+pub async fn start(test: &Test, page: usize, cookies: &CookieJar<'_>, user: Option<User>, pool: &State<PgPool>) -> Redirect {
     let resp_id_cookie_name = format!("responseId[{}]", test.id);
-    let response_id = if cookies.get(&resp_id_cookie_name).is_none() {
-        let gen_id = Uuid::new_v4();
-        cookies.add(Cookie::new(resp_id_cookie_name.to_string(), gen_id.to_string()));
-        gen_id
+    let current: Option<Uuid> = cookies.get(&resp_id_cookie_name).and_then(|cookie| cookie.value().parse().ok());
+    let mut conn = pool.acquire().await.unwrap();
+    let in_progress = match current {
+        Some(response_id) => matches!(database::get_attempt(response_id, &mut conn).await,
+            Some((Some(test_id), false)) if test_id == test.id),
+        None => false,
+    };
+    let response_id = match current {
+        Some(response_id) if in_progress => response_id,
+        _ if test.informant_of.is_some() => return Redirect::to(uri!(super::index::index)),
+        _ => start_attempt(test, cookies, user, &mut conn).await,
+    };
+    attempt_page(test, response_id, page)
+}
+
+#[post("/test/<test>/new")]
+pub async fn new_attempt(test: &Test, cookies: &CookieJar<'_>, user: Option<User>, pool: &State<PgPool>) -> Redirect {
+    if test.informant_of.is_some() {
+        return Redirect::to(uri!(super::index::index));
     }
-    else {
-        cookies.get(&resp_id_cookie_name).unwrap().value().parse().unwrap()
+    attempt_page(test, start_attempt(test, cookies, user, &mut pool.acquire().await.unwrap()).await, 0)
+}
+
+#[post("/test/<test>/<id>/<token>/<page>/cat", data="<response>")]
+pub async fn post_adaptive(test: &Test, id: &str, token: &str, page: usize, user: Option<User>, response: Form<Response>, pool: &State<PgPool>) -> Result<Redirect, Custom<String>> {
+    let mut conn = pool.acquire().await.unwrap();
+    let response_id = match open_attempt(test, id, token, &mut conn).await {
+        Ok(response_id) => response_id,
+        Err(redirect) => return Ok(redirect),
+    };
+    save_page(test, page, response_id, &response, user, &mut conn).await.map_err(invalid_page)?;
+    Ok(attempt_page(test, response_id, page))
+}
+
+#[post("/test/<test>/<id>/<token>/<page>", data="<response>")]
+pub async fn post_test(test: &Test, id: &str, token: &str, page: usize, response: Form<Response>, pool: &State<PgPool>) -> Result<Redirect, Custom<String>> {
+    let mut conn = pool.acquire().await.unwrap();
+    let response_id = match open_attempt(test, id, token, &mut conn).await {
+        Ok(response_id) => response_id,
+        Err(redirect) => return Ok(redirect),
+    };
+    let submitted = page.checked_sub(1).ok_or_else(|| invalid_page("There is no page before the first.".into()))?;
+    save_page(test, submitted, response_id, &response, None, &mut conn).await.map_err(invalid_page)?;
+    Ok(attempt_page(test, response_id, page))
+}
+
+#[post("/test/<test>/<id>/<token>/<page>/back", data="<response>")]
+pub async fn post_back(test: &Test, id: &str, token: &str, page: usize, response: Form<Response>, pool: &State<PgPool>) -> Result<Redirect, Custom<String>> {
+    let mut conn = pool.acquire().await.unwrap();
+    let response_id = match open_attempt(test, id, token, &mut conn).await {
+        Ok(response_id) => response_id,
+        Err(redirect) => return Ok(redirect),
     };
+    save_page(test, page, response_id, &response, None, &mut conn).await.map_err(invalid_page)?;
+    let resp = database::get_or_create_response(response_id, &test.id, None, &mut conn).await;
+    Ok(attempt_page(test, response_id, previous_page(test, page, &resp).unwrap_or(0)))
+}
+
+#[get("/resume/<id>/<token>")]
+pub async fn get_resume(id: &str, token: &str, cookies: &CookieJar<'_>, pool: &State<PgPool>) -> Option<Redirect> {
+    let response_id: Uuid = id.parse().ok()?;
+    if !resume::verify(response_id, token) {
+        return None;
+    }
+    let mut conn = pool.acquire().await.unwrap();
+    let stored = database::get_stored_response(response_id, &mut conn).await?;
+    let test = get_test(stored.test_id.as_deref()?)?;
+    if stored.completed {
+        return Some(feedback_redirect(test, response_id));
+    }
+    cookies.add(Cookie::new(format!("responseId[{}]", test.id), id.to_string()));
+    Some(attempt_page(test, response_id, stored.last_page))
+}
+
+// Rocket passes every guard as an argument.
+#[allow(clippy::too_many_arguments)]
+#[get("/test/<test>/<id>/<token>/<page>")]
+pub async fn test(test: &Test, id: &str, token: &str, page: usize, client: ClientInfo, user: Option<User>, durations: &State<Durations>, pool: &State<PgPool>) -> Option<Result<Template, Redirect>> {
+    if page >= test.pages.len() {
+        return None;
+    }
     let mut conn = pool.acquire().await.unwrap();
-    let resp = database::get_or_create_response(response_id, &mut conn).await;
-    let show = test.pages[page].condition.eval(resp);
-    if show {
-        Ok(Template::render("test.html", &TemplateContext {
+    let response_id = match open_attempt(test, id, token, &mut conn).await {
+        Ok(response_id) => response_id,
+        Err(redirect) => return Some(Err(redirect)),
+    };
+    let resp = database::get_or_create_response(response_id, &test.id, user.map(|user| user.user_id), &mut conn).await;
+    let elements = shown_elements(test, page, response_id, &to_value(&resp).unwrap());
+    let show = test.pages[page].condition.holds(&resp) == Some(true);
+    if let (true, Some(elements)) = (show, elements) {
+        let values = elements.iter()
+            .filter_map(|q| Some(q.form_values(resp.get(&q.id)?)))
+            .flatten()
+            .collect();
+        let mut data = database::get_paradata(response_id, &mut conn).await;
+        paradata::served(&mut data, page, &client);
+        database::set_paradata(response_id, data, &mut conn).await;
+        database::reach_page(response_id, page, &mut conn).await;
+        Some(Ok(Template::render("test.html", &TemplateContext {
             title: &test.name,
             style_hash: &style_hash().await,
-            data: TestContext { test: test, page: page },
-        }))
+            data: TestContext {
+                test: test,
+                page: page,
+                elements,
+                values,
+                adaptive: test.adaptive(page).is_some(),
+                progress: progress::progress(test, page, &resp, durations.of(test)),
+                previous: previous_page(test, page, &resp),
+                response_id: response_id.to_string(),
+                token: token.to_string(),
+            },
+        })))
     }
     else {
-        if page + 1 < test.pages.len() {
-            Err(Redirect::to(uri!(test(test=test, page=page+1))))
-        }
-        else {
-            cookies.remove(Cookie::named(resp_id_cookie_name));
-            Err(Redirect::to(uri!(get_feedback(test=test, id=response_id.to_string()))))
+        match next_page(test, page + 1, response_id, &resp) {
+            Some(next) => Some(Err(attempt_page(test, response_id, next))),
+            None => {
+                database::complete_response(response_id, &mut conn).await;
+                Some(Err(feedback_redirect(test, response_id)))
+            }
         }
     }
 }
 
-#[post("/feedback/<test>", data="<response>")]
-pub async fn post_feedback(test: &Test, cookies: &CookieJar<'_>, response: Form<Response>, pool: &State<PgPool>) -> Redirect {
-    let resp_id_cookie_name = format!("responseId[{}]", test.id);
-    let response_id: Uuid = cookies.get(&resp_id_cookie_name).unwrap().value().parse().unwrap();
-    println!("{:?}", &response.questions);
-    let resp_map = get_resp_map(test, test.pages.len()-1, &response);
-    database::update_response(response_id, resp_map, &mut pool.acquire().await.unwrap()).await;
-    cookies.remove(Cookie::named(resp_id_cookie_name));
-    Redirect::to(uri!(get_feedback(test=test, id=response_id.to_string())))
-}
-#[get("/feedback/<test>/<id>")]
-pub async fn get_feedback(test: &Test, pool: &State<PgPool>, id: &str) -> Template {
-    let response_id: Uuid = id.parse().unwrap();
+#[post("/test/<test>/<id>/<token>/<page>/finish", data="<response>")]
+pub async fn post_feedback(test: &Test, id: &str, token: &str, page: usize, response: Form<Response>, pool: &State<PgPool>) -> Result<Redirect, Custom<String>> {
     let mut conn = pool.acquire().await.unwrap();
-    let res = database::get_response(response_id, &mut conn).await;
+    let response_id = match open_attempt(test, id, token, &mut conn).await {
+        Ok(response_id) => response_id,
+        Err(redirect) => return Ok(redirect),
+    };
+    save_page(test, page, response_id, &response, None, &mut conn).await.map_err(invalid_page)?;
+    database::complete_response(response_id, &mut conn).await;
+    Ok(feedback_redirect(test, response_id))
+}
+
+// The response whose feedback the token opens.
+fn owned_response(id: &str, token: &str) -> Option<Uuid> {
+    let response_id: Uuid = id.parse().ok()?;
+    sharing::verify_feedback(response_id, token).then_some(response_id)
+}
+
+pub(super) fn score_feedback(test: &Test, res: &Value) -> Vec<FeedbackItem> {
     let mut feedback = vec![];
     for part in &test.feedback {
-        feedback.push(part.score(&res));
+        feedback.extend(part.score(test, res));
     }
-    Template::render("feedback.html", &TemplateContext {
+    feedback
+}
+
+#[get("/feedback/<test>/<id>/<token>?<unknown_code>&<unknown_partner>")]
+pub async fn get_feedback(test: &Test, pool: &State<PgPool>, id: &str, token: &str, unknown_code: Option<bool>, unknown_partner: Option<bool>, cookies: &CookieJar<'_>) -> Option<Template> {
+    let response_id = owned_response(id, token)?;
+    let mut conn = pool.acquire().await.unwrap();
+    let res = database::get_response(response_id, &mut conn).await?;
+    let withdrawal_code = withdrawal::issue_code(response_id, &mut conn).await;
+    let retest_code = match test.informant_of {
+        None => {
+            retest::remember(test, response_id, cookies);
+            Some(retest::issue_code(response_id, &mut conn).await)
+        }
+        Some(_) => None,
+    };
+    let informant = informant::feedback(test, response_id, &res, &mut conn).await;
+    let compatibility = match test.informant_of {
+        None => Some(compatibility::section(response_id, &mut conn).await),
+        Some(_) => None,
+    };
+    let shares = match test.informant_of {
+        None => Some(sharing::links(response_id, &mut conn).await),
+        Some(_) => None,
+    };
+    let attempts = database::get_attempts(response_id, &mut conn).await;
+    let feedback = score_feedback(test, &res);
+    Some(Template::render("feedback.html", &TemplateContext {
         title: "Feedback",
         style_hash: &style_hash().await,
         data: FeedbackContext {
-            feedback: &feedback
+            feedback: &feedback,
+            test_id: &test.id,
+            response_id: response_id.to_string(),
+            token: token.to_string(),
+            changes: retest::changes(test, &attempts, response_id),
+            retest_code,
+            unknown_code: unknown_code.unwrap_or(false),
+            informant,
+            compatibility,
+            unknown_partner: unknown_partner.unwrap_or(false),
+            shares,
+            withdrawal_code,
+            withdrawal_signature: withdrawal::link_signature(response_id),
+            access_token: access::token(response_id),
         }
-    })
-}
\ No newline at end of file
+    }))
+}
+
+#[derive(FromForm)]
+pub struct RetestForm {
+    code: String,
+}
+
+#[post("/feedback/<test>/<id>/<token>/retest", data = "<form>")]
+pub async fn post_retest(test: &Test, id: &str, token: &str, form: Form<RetestForm>, cookies: &CookieJar<'_>, user: Option<User>, pool: &State<PgPool>) -> Option<Redirect> {
+    let response_id = owned_response(id, token)?;
+    let joined = retest::join(test, response_id, &form.code, cookies, user, &mut pool.acquire().await.unwrap()).await;
+    let unknown_code = if joined { None } else { Some(true) };
+    Some(Redirect::to(uri!(get_feedback(test=test, id=id, token=token, unknown_code=unknown_code, unknown_partner=_))))
+}
+
+#[derive(FromForm)]
+pub struct CompatibilityForm {
+    code: String,
+}
+
+#[post("/feedback/<test>/<id>/<token>/compare", data = "<form>")]
+pub async fn post_compare(test: &Test, id: &str, token: &str, form: Form<CompatibilityForm>, pool: &State<PgPool>) -> Option<Redirect> {
+    let response_id = owned_response(id, token)?;
+    let linked = compatibility::link(test, response_id, &form.code, &mut pool.acquire().await.unwrap()).await;
+    let unknown_partner = if linked { None } else { Some(true) };
+    Some(Redirect::to(uri!(get_feedback(test=test, id=id, token=token, unknown_code=_, unknown_partner=unknown_partner))))
+}
+
+#[get("/feedback/<test>/<id>/<token>/compare/<other>")]
+pub async fn compare(test: &Test, id: &str, token: &str, other: &str, pool: &State<PgPool>) -> Option<Template> {
+    let response_id = owned_response(id, token)?;
+    let other_id: Uuid = other.parse().ok()?;
+    let mut conn = pool.acquire().await.unwrap();
+    if !database::is_mutually_linked(response_id, other_id, &mut conn).await {
+        return None;
+    }
+    let own = database::get_response(response_id, &mut conn).await?;
+    let other = database::get_response(other_id, &mut conn).await?;
+    Some(Template::render("compare.html", &TemplateContext {
+        title: "Comparison",
+        style_hash: &style_hash().await,
+        data: CompareContext {
+            test_id: &test.id,
+            response_id: id,
+            token,
+            comparison: compatibility::compare(test, &own, &other),
+        },
+    }))
+}
+
+#[derive(FromForm)]
+pub struct ShareForm {
+    summary: bool,
+    expires_days: Option<i32>,
+}
+
+#[post("/feedback/<test>/<id>/<token>/share", data = "<form>")]
+pub async fn post_share(test: &Test, id: &str, token: &str, form: Form<ShareForm>, pool: &State<PgPool>) -> Option<Redirect> {
+    let response_id = owned_response(id, token)?;
+    let days = form.expires_days.filter(|days| *days > 0);
+    sharing::create(response_id, form.summary, days, &mut pool.acquire().await.unwrap()).await;
+    Some(Redirect::to(uri!(get_feedback(test=test, id=id, token=token, unknown_code=_, unknown_partner=_))))
+}
+
+#[post("/feedback/<test>/<id>/<token>/share/<share>/revoke")]
+pub async fn revoke_share(test: &Test, id: &str, token: &str, share: &str, pool: &State<PgPool>) -> Option<Redirect> {
+    let response_id = owned_response(id, token)?;
+    let share_id: Uuid = share.parse().ok()?;
+    database::revoke_share(response_id, share_id, &mut pool.acquire().await.unwrap()).await;
+    Some(Redirect::to(uri!(get_feedback(test=test, id=id, token=token, unknown_code=_, unknown_partner=_))))
+}
+
+#[get("/shared/<share>/<signature>")]
+pub async fn shared(share: &str, signature: &str, pool: &State<PgPool>) -> Option<Template> {
+    let share_id: Uuid = share.parse().ok()?;
+    let mut conn = pool.acquire().await.unwrap();
+    let (response_id, summary) = sharing::resolve(share_id, signature, &mut conn).await?;
+    let test = get_test(&database::get_test_id(response_id, &mut conn).await?)?;
+    let res = database::get_response(response_id, &mut conn).await?;
+    Some(Template::render("shared.html", &TemplateContext {
+        title: "Shared Results",
+        style_hash: &style_hash().await,
+        data: SharedContext {
+            test_name: &test.name,
+            summary,
+            feedback: sharing::shared_feedback(score_feedback(test, &res), summary),
+        },
+    }))
+}
+
+// Starts a report on the invite's target, or continues the one in progress in this browser. An
+// informant who already finished their report is thanked rather than given another one.
+#[get("/informant/<id>/<signature>")]
+pub async fn informant_invite(id: &str, signature: &str, cookies: &CookieJar<'_>, pool: &State<PgPool>) -> Option<Result<Redirect, Template>> {
+    let target_id: Uuid = id.parse().ok()?;
+    if !informant::verify_invite(target_id, signature) {
+        return None;
+    }
+    let mut conn = pool.acquire().await.unwrap();
+    let variant = informant::variant(target_id, &mut conn).await?;
+    let resp_id_cookie_name = format!("responseId[{}]", variant.id);
+    let current: Option<Uuid> = cookies.get(&resp_id_cookie_name).and_then(|cookie| cookie.value().parse().ok());
+    let report = match current {
+        Some(response_id) if database::get_target(response_id, &mut conn).await == Some(target_id) => Some(response_id),
+        _ => None,
+    };
+    let response_id = match report {
+        Some(response_id) => {
+            if let Some((_, true)) = database::get_attempt(response_id, &mut conn).await {
+                return Some(Err(Template::render("informant_done.html", &TemplateContext {
+                    title: &variant.name,
+                    style_hash: &style_hash().await,
+                    data: (),
+                })));
+            }
+            response_id
+        }
+        None => {
+            let response_id = informant::start(variant, target_id, &mut conn).await;
+            cookies.add(Cookie::new(resp_id_cookie_name, response_id.to_string()));
+            response_id
+        }
+    };
+    Some(Ok(attempt_page(variant, response_id, 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn attention_checks_go_in_front_of_an_item() {
+        let test = make_tipi_test();
+        let elements = &test.pages.iter().find(|page| page.elements.iter().any(|q| q.is_attention_check())).unwrap().elements;
+        let mut positions = HashSet::new();
+        for i in 0..20 {
+            let response_id = Uuid::from_u128(i * 7919);
+            let placed = place_attention_checks(elements, response_id);
+            let ids: Vec<&str> = placed.iter().map(|q| q.id.as_str()).collect();
+            assert_eq!(ids, place_attention_checks(elements, response_id).iter().map(|q| q.id.as_str()).collect::<Vec<_>>());
+            let mut sorted = ids.clone();
+            sorted.sort_unstable();
+            let mut expected: Vec<&str> = elements.iter().map(|q| q.id.as_str()).collect();
+            expected.sort_unstable();
+            assert_eq!(sorted, expected);
+
+            // The check keeps its label and is followed by another item's label and the item.
+            let pos = placed.iter().position(|q| q.is_attention_check()).unwrap();
+            assert!(matches!(placed[pos - 1].content, QuestionContent::AlignText { .. }));
+            assert!(matches!(placed[pos + 1].content, QuestionContent::AlignText { .. }));
+            assert!(matches!(placed[pos + 2].content, QuestionContent::McQuestion { expected: None, .. }));
+            positions.insert(pos);
+        }
+        assert!(positions.len() > 1);
+    }
+
+    #[test]
+    fn attention_checks_need_one_of_their_options() {
+        let test = make_tipi_test();
+        let check = test.pages.iter().flat_map(|page| &page.elements).find(|q| q.is_attention_check()).unwrap();
+        let answer = |value: &str| Response {
+            questions: HashMap::from([(check.id.clone(), value.to_string())]),
+            paradata: HashMap::new(),
+        };
+        for value in ["7", "-1", "x", ""] {
+            assert!(get_resp_map(&[check], &answer(value)).is_err(), "{}", value);
+        }
+        let stored = get_resp_map(&[check], &answer("6")).unwrap();
+        assert_eq!(stored[&check.id]["ord"], 6);
+    }
+
+    #[test]
+    fn pages_past_the_last_are_not_shown() {
+        let test = make_tipi_test();
+        let response_id = Uuid::from_u128(1);
+        assert!(shown_elements(&test, 0, response_id, &json!({})).is_some());
+        assert!(shown_elements(&test, test.pages.len(), response_id, &json!({})).is_none());
+    }
+}
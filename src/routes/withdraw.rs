@@ -0,0 +1,125 @@
+use rocket::State;
+use rocket::form::Form;
+use rocket::serde::Serialize;
+use rocket_dyn_templates::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::database;
+use crate::withdrawal::{self, Action};
+use super::{TemplateContext, style_hash};
+
+pub static TEMPLATE: &str = r#"
+{% extends "base" %}
+
+{% block content %}
+    <h1>Withdraw Your Response</h1>
+    {% if data.stage == "code" %}
+        <p>Enter the withdrawal code you were given with your feedback.</p>
+        {% if data.error %}<p class="error">{{ data.error }}</p>{% endif %}
+        <form action="/withdraw" method="post">
+            <input type="text" name="code" placeholder="XXXX-XXXX-XXXX-XXXX-XXXX">
+            <input type="submit" value="Continue">
+        </form>
+    {% elif data.stage == "choose" %}
+        {% if data.consent_withdrawn %}
+            <p>You have already withdrawn your consent, so your response is not used for research.
+            You can still delete it entirely.</p>
+        {% else %}
+            <p>You can withdraw your consent, so that your response is no longer used for research
+            or published in any dataset, while you can still see your feedback. Or you can delete
+            your response entirely, including your feedback.</p>
+        {% endif %}
+        <form action="/withdraw/{{ data.response_id }}/{{ data.signature }}" method="post">
+            <input type="hidden" name="via" value="{{ data.via }}">
+            {% if not data.consent_withdrawn %}
+                <button type="submit" name="action" value="revoke">Withdraw consent</button>
+            {% endif %}
+            <button type="submit" name="action" value="delete">Delete my response</button>
+        </form>
+    {% elif data.stage == "revoke" %}
+        <p>Your consent has been withdrawn. Your response will no longer be used for research.</p>
+    {% elif data.stage == "delete" %}
+        <p>Your response has been deleted.</p>
+    {% endif %}
+{% endblock content %}
+"#;
+
+#[derive(Serialize, Default)]
+#[serde(crate = "rocket::serde")]
+struct WithdrawContext {
+    stage: &'static str,
+    error: Option<&'static str>,
+    response_id: String,
+    signature: String,
+    via: &'static str,
+    consent_withdrawn: bool,
+}
+
+#[derive(FromForm)]
+pub struct CodeForm {
+    code: String,
+}
+
+#[derive(FromForm)]
+pub struct ActionForm {
+    action: Action,
+    via: String,
+}
+
+async fn render(data: WithdrawContext) -> Template {
+    Template::render("withdraw.html", &TemplateContext {
+        title: "Withdraw",
+        style_hash: &style_hash().await,
+        data,
+    })
+}
+
+async fn choose(response_id: Uuid, via: &'static str, pool: &PgPool) -> Option<Template> {
+    let (_, consent_withdrawn) = database::get_withdrawal_state(response_id, &mut pool.acquire().await.unwrap()).await?;
+    Some(render(WithdrawContext {
+        stage: "choose",
+        response_id: response_id.to_string(),
+        signature: withdrawal::link_signature(response_id),
+        via,
+        consent_withdrawn,
+        ..WithdrawContext::default()
+    }).await)
+}
+
+#[get("/withdraw")]
+pub async fn withdraw_form() -> Template {
+    render(WithdrawContext { stage: "code", ..WithdrawContext::default() }).await
+}
+
+#[post("/withdraw", data = "<form>")]
+pub async fn withdraw_code(form: Form<CodeForm>, pool: &State<PgPool>) -> Template {
+    let response_id = withdrawal::find_by_code(&form.code, &mut pool.acquire().await.unwrap()).await;
+    match response_id {
+        Some(response_id) => choose(response_id, "code", pool).await.unwrap(),
+        None => render(WithdrawContext {
+            stage: "code",
+            error: Some("No response has this withdrawal code."),
+            ..WithdrawContext::default()
+        }).await,
+    }
+}
+
+#[get("/withdraw/<id>/<signature>")]
+pub async fn withdraw_link(id: &str, signature: &str, pool: &State<PgPool>) -> Option<Template> {
+    let response_id: Uuid = id.parse().ok()?;
+    if !withdrawal::verify_link(response_id, signature) {
+        return None;
+    }
+    choose(response_id, "link", pool).await
+}
+
+#[post("/withdraw/<id>/<signature>", data = "<form>")]
+pub async fn post_withdraw(id: &str, signature: &str, form: Form<ActionForm>, pool: &State<PgPool>) -> Option<Template> {
+    let response_id: Uuid = id.parse().ok()?;
+    if !withdrawal::verify_link(response_id, signature) {
+        return None;
+    }
+    let via = if form.via == "code" { "code" } else { "link" };
+    withdrawal::withdraw(response_id, form.action, via, &mut pool.acquire().await.unwrap()).await;
+    Some(render(WithdrawContext { stage: form.action.name(), ..WithdrawContext::default() }).await)
+}
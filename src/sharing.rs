@@ -0,0 +1,100 @@
+use rocket::serde::Serialize;
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::database;
+use crate::signing;
+use crate::tests::FeedbackItem;
+use crate::util::format_timestamp;
+
+// Feedback links. A respondent's own feedback page is reached through a token signed for their
+// response, so knowing a response id is not enough to see its results. To show their results to
+// others, respondents create share links, each signed, optionally expiring and revocable. A full
+// share shows the feedback without the respondent's codes and links; a public summary shows only
+// the scale names and scores.
+
+const FEEDBACK_PURPOSE: &str = "feedback";
+const SHARE_PURPOSE: &str = "share";
+
+/// The token of the respondent's own feedback page.
+pub fn feedback_token(response_id: Uuid) -> String {
+    signing::sign(FEEDBACK_PURPOSE, &response_id.to_string())
+}
+
+pub fn verify_feedback(response_id: Uuid, token: &str) -> bool {
+    signing::verify(FEEDBACK_PURPOSE, &response_id.to_string(), token)
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ShareLink {
+    pub share_id: String,
+    pub signature: String,
+    pub summary: bool,
+    pub expires: Option<String>,
+}
+
+/// Creates a share of the response, expiring after `days` days if given, and returns its id.
+pub async fn create(response_id: Uuid, summary: bool, days: Option<i32>, conn: &mut PoolConnection<Postgres>) -> Uuid {
+    let share_id = Uuid::new_v4();
+    database::insert_share(share_id, response_id, summary, days, conn).await;
+    share_id
+}
+
+/// The response's shares that are neither revoked nor expired.
+pub async fn links(response_id: Uuid, conn: &mut PoolConnection<Postgres>) -> Vec<ShareLink> {
+    database::get_active_shares(response_id, conn).await.into_iter()
+        .map(|(share_id, summary, expires)| ShareLink {
+            share_id: share_id.to_string(),
+            signature: signing::sign(SHARE_PURPOSE, &share_id.to_string()),
+            summary,
+            expires: expires.map(format_timestamp),
+        })
+        .collect()
+}
+
+/// The shared response and whether only its summary is shared, if the link is valid and the
+/// share neither revoked nor expired.
+pub async fn resolve(share_id: Uuid, signature: &str, conn: &mut PoolConnection<Postgres>) -> Option<(Uuid, bool)> {
+    if !signing::verify(SHARE_PURPOSE, &share_id.to_string(), signature) {
+        return None;
+    }
+    database::get_active_share(share_id, conn).await
+}
+
+/// The feedback as shown to others: a public summary leaves out everything but titles and scores.
+pub fn shared_feedback(feedback: Vec<FeedbackItem>, summary: bool) -> Vec<FeedbackItem> {
+    feedback.into_iter()
+        .filter(|item| !summary || matches!(item, FeedbackItem::Title { .. } | FeedbackItem::Bar { .. }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feedback_tokens_open_only_their_response() {
+        let token = feedback_token(Uuid::from_u128(1));
+        assert!(verify_feedback(Uuid::from_u128(1), &token));
+        assert!(!verify_feedback(Uuid::from_u128(2), &token));
+        assert!(!verify_feedback(Uuid::from_u128(1), ""));
+        // A share link's signature is not the owner's token.
+        let share = signing::sign(SHARE_PURPOSE, &Uuid::from_u128(1).to_string());
+        assert!(!verify_feedback(Uuid::from_u128(1), &share));
+    }
+
+    #[test]
+    fn summaries_keep_only_titles_and_scores() {
+        let feedback = || vec![
+            FeedbackItem::Title { text: "Extraversion".into() },
+            FeedbackItem::Paragraph { text: "You enjoy company.".into() },
+            FeedbackItem::Bar { score: 3.0, min: 1.0, max: 5.0 },
+        ];
+        let summary = shared_feedback(feedback(), true);
+        assert_eq!(summary.len(), 2);
+        assert!(matches!(summary[0], FeedbackItem::Title { .. }));
+        assert!(matches!(summary[1], FeedbackItem::Bar { .. }));
+        assert_eq!(shared_feedback(feedback(), false).len(), 3);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+// HMAC-SHA256 signatures for links and codes handed out to respondents, such as withdrawal
+// links. The server secret is created on first use and kept in ./secret.key; replacing it
+// invalidates every signature handed out so far. Each use signs under its own purpose, so a
+// signature made for one purpose is never accepted for another.
+
+const SECRET_PATH: &str = "./secret.key";
+// Codes are 20 hex digits, or 80 bits.
+const CODE_DIGITS: usize = 20;
+
+lazy_static! {
+    static ref SECRET: Vec<u8> = load_or_create_secret();
+}
+
+fn load_or_create_secret() -> Vec<u8> {
+    match fs::read(SECRET_PATH) {
+        Ok(secret) => secret,
+        Err(_) => {
+            let secret: [u8; 32] = rand::random();
+            let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(SECRET_PATH).unwrap();
+            file.write_all(&secret).unwrap();
+            secret.to_vec()
+        }
+    }
+}
+
+fn mac(purpose: &str, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&SECRET).unwrap();
+    mac.update(purpose.as_bytes());
+    mac.update(&[0]);
+    mac.update(message.as_bytes());
+    mac
+}
+
+/// The signature of `message` for `purpose`, as lowercase hex.
+pub fn sign(purpose: &str, message: &str) -> String {
+    mac(purpose, message).finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn verify(purpose: &str, message: &str, signature: &str) -> bool {
+    let bytes: Option<Vec<u8>> = (0..signature.len()).step_by(2)
+        .map(|i| signature.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect();
+    bytes.is_some_and(|bytes| mac(purpose, message).verify_slice(&bytes).is_ok())
+}
+
+/// A code derived from the signature, in groups of four hex digits for writing down.
+pub fn code(purpose: &str, message: &str) -> String {
+    let digits = sign(purpose, message).to_uppercase();
+    let groups: Vec<&str> = (0..CODE_DIGITS).step_by(4).map(|i| &digits[i..i + 4]).collect();
+    groups.join("-")
+}
+
+/// The hash under which a code is stored and looked up, ignoring case, spaces and dashes.
+pub fn code_hash(code: &str) -> String {
+    let normalized: String = code.chars().filter(char::is_ascii_hexdigit).collect::<String>().to_uppercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_only_for_their_purpose_and_message() {
+        let signature = sign("purpose", "message");
+        assert_eq!(signature.len(), 64);
+        assert!(signature.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+        assert!(verify("purpose", "message", &signature));
+        assert!(!verify("other", "message", &signature));
+        assert!(!verify("purpose", "other", &signature));
+        // The purpose and message are kept apart, so they can't be shifted into each other.
+        assert!(!verify("purposem", "essage", &signature));
+    }
+
+    #[test]
+    fn malformed_signatures_are_rejected() {
+        let signature = sign("purpose", "message");
+        let tampered: String = signature.chars().rev().collect();
+        for bad in ["", "0", &signature[1..], &signature[..62], &tampered, &signature.replace(&signature[..1], "g")] {
+            assert!(!verify("purpose", "message", bad), "{}", bad);
+        }
+        assert!(verify("purpose", "message", &signature.to_uppercase()));
+    }
+
+    #[test]
+    fn codes_are_grouped_and_looked_up_loosely() {
+        let code = code("purpose", "message");
+        assert_eq!(code.len(), CODE_DIGITS / 4 * 5 - 1);
+        assert!(code.split('-').all(|group| group.len() == 4));
+        assert_ne!(code, self::code("purpose", "other"));
+        let loose = format!(" {} ", code.to_lowercase().replace('-', " "));
+        assert_eq!(code_hash(&loose), code_hash(&code));
+    }
+}
@@ -3,27 +3,163 @@ use std::convert::Infallible;
 use rocket::http::uri::fmt::{FromUriParam, Part};
 use rocket::request::{FromParam};
 use rocket::serde::{Serialize, Serializer};
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
 use serde_json::{json, Value};
 use lazy_static::lazy_static;
+use crate::cat::Adaptive;
+use crate::irt::{Calibration, GrmItem, eap};
 use crate::util::contains;
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct Test {
     pub id: String,
     pub name: String,
     pub pages: Vec<TestPage>,
     pub feedback: Vec<FeedbackItem>,
+    pub scales: Vec<Scale>,
+    pub calibration: Option<Calibration>,
+    /// The checkbox through which respondents consent to their response being published, if any.
+    pub consent: Option<String>,
+    /// Questions whose answers are kept private even if the respondent consented to publication.
+    pub private: Vec<String>,
+    /// Questions that could identify a respondent in combination with each other, such as
+    /// demographics. Public releases coarsen them until every combination is shared by several
+    /// respondents.
+    pub demographics: Vec<String>,
+    /// The other-report variant of a self-report test, if it has one.
+    pub informant: Option<Informant>,
+    /// For an other-report variant, the self-report test it describes the target of.
+    pub informant_of: Option<String>,
 }
 
-#[derive(Serialize)]
+/// How a self-report test is paired with its other-report variant, which shares its item ids and
+/// scales so that informant reports are scored the same way.
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Informant {
+    pub test: String,
+    /// The number of reports needed before their average is shown to the target, and the size of
+    /// the batches in which later reports are added to it, so that no single informant's answers
+    /// can be made out.
+    pub min_reports: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Scale {
+    pub id: String,
+    pub name: String,
+    pub categories: usize,
+    pub items: Vec<ScaleItem>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ScaleItem {
+    pub id: String,
+    pub reversed: bool,
+}
+
+impl Scale {
+    /// The keyed (reverse-scored where needed) 0-based category of each item, if answered.
+    pub fn keyed_answers(&self, resp: &Value) -> Vec<Option<usize>> {
+        self.items.iter().map(|item| {
+            resp[&item.id]["ord"].as_u64().map(|ord| {
+                let ord = ord as usize;
+                if item.reversed { self.categories - 1 - ord } else { ord }
+            })
+        }).collect()
+    }
+
+    /// The mean keyed answer on the 1..=categories scale the respondent saw.
+    pub fn mean(&self, resp: &Value) -> Option<f64> {
+        let answers: Vec<usize> = self.keyed_answers(resp).into_iter().flatten().collect();
+        if answers.is_empty() {
+            None
+        }
+        else {
+            Some(1.0 + answers.iter().sum::<usize>() as f64 / answers.len() as f64)
+        }
+    }
+}
+
+impl Test {
+    /// The questions that take an answer, in order, each with the text shown just before it (its
+    /// `AlignText` or the closest header), or with its id if there is none.
+    pub fn questions(&self) -> Vec<(&Question, &str)> {
+        let mut questions = vec![];
+        let mut prompt: Option<&str> = None;
+        for q in self.pages.iter().flat_map(|page| &page.elements) {
+            match &q.content {
+                QuestionContent::AlignText { text } => prompt = Some(text),
+                QuestionContent::Header { title, .. } => prompt = Some(title),
+                QuestionContent::Paragraph { .. } => {}
+                _ => questions.push((q, prompt.unwrap_or(&q.id))),
+            }
+            if !q.id.is_empty() {
+                prompt = None;
+            }
+        }
+        questions
+    }
+
+    pub fn scale(&self, id: &str) -> Option<&Scale> {
+        self.scales.iter().find(|scale| scale.id == id)
+    }
+
+    /// How a page is administered adaptively, with the calibrated items of its scale. A page
+    /// whose scale hasn't been calibrated, or whose calibration doesn't list the scale's items as
+    /// they are on the page, is shown all at once like any other.
+    pub fn adaptive(&self, page: usize) -> Option<(&Adaptive, &Vec<GrmItem>)> {
+        let page = self.pages.get(page)?;
+        let adaptive = page.adaptive.as_ref()?;
+        let items = self.calibration.as_ref()?.scales.get(&adaptive.scale)?;
+        let scale = self.scale(&adaptive.scale)?;
+        let fits = items.len() == scale.items.len()
+            && items.iter().zip(&scale.items).all(|(calibrated, item)| calibrated.id == item.id)
+            && items.iter().all(|item| page.elements.iter().any(|q| q.id == item.id));
+        fits.then_some((adaptive, items))
+    }
+
+    /// The scale's score as a percentage of the range of its bar, if any of its items were
+    /// answered.
+    pub fn scale_percentage(&self, id: &str, resp: &Value) -> Option<f64> {
+        match self.score_scale(id, resp)? {
+            FeedbackItem::Bar { score, min, max } => Some(100.0 * (score - min) / (max - min)),
+            _ => None,
+        }
+    }
+
+    /// Scores a scale as a bar: the EAP trait estimate if the scale has been calibrated, and the
+    /// mean item score otherwise. A scale none of whose items were answered isn't scored.
+    pub fn score_scale(&self, id: &str, resp: &Value) -> Option<FeedbackItem> {
+        let scale = self.scale(id)?;
+        let mean = scale.mean(resp)?;
+        let params = self.calibration.as_ref().and_then(|cal| cal.scales.get(id));
+        Some(match params {
+            Some(items) => {
+                let estimate = eap(items, &scale.keyed_answers(resp));
+                FeedbackItem::Bar { score: estimate.theta.clamp(-3.0, 3.0), min: -3.0, max: 3.0 }
+            }
+            None => FeedbackItem::Bar { score: mean, min: 1.0, max: scale.categories as f64 },
+        })
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct TestPage {
     pub condition: Condition,
+    /// If set, the page is an item bank for a calibrated scale, and its items are presented one
+    /// at a time in adaptive order instead of all at once.
+    pub adaptive: Option<Adaptive>,
     pub elements: Vec<Question>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub enum Condition {
     Always,
@@ -39,48 +175,89 @@ impl Condition {
             }
         }
     }
+
+    /// Like `eval`, but `None` if the condition depends on a question that hasn't been answered.
+    pub fn holds(&self, resp: &HashMap<String, Value>) -> Option<bool> {
+        match &self {
+            Condition::Always => Some(true),
+            Condition::Question { id, value } => Some(contains(value, resp.get(id)?)),
+        }
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct Question {
     pub id: String,
     pub content: QuestionContent
 }
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub enum QuestionContent {
     Header { title: String, size: i8 },
     Paragraph { text: String },
     AlignText { text : String },
-    McQuestion { options: Vec<String> },
+    /// If `expected` is set, the question is an instructed-response attention check: it is
+    /// placed at a random position among the page's items and records whether the expected option
+    /// was chosen, along with the option that was.
+    McQuestion {
+        options: Vec<String>,
+        #[serde(skip_serializing)]
+        #[schemars(skip)]
+        expected: Option<usize>,
+    },
     McQuestionVert { options: Vec<String>, other: bool },
     CheckboxQuestion { text: String },
     TextAreaQuestion,
 }
 impl Question {
-    pub fn convert(&self, resp: &HashMap<String, String>) -> Option<Value> {
+    pub fn is_attention_check(&self) -> bool {
+        matches!(self.content, QuestionContent::McQuestion { expected: Some(_), .. })
+    }
+
+    /// Checks the form values for the question: a choice must be the index of one of its options,
+    /// and a text area must be submitted, if only empty.
+    pub fn validate(&self, resp: &HashMap<String, String>) -> Result<(), String> {
         use QuestionContent::*;
-        match &self.content {
+        let answer = resp.get(&self.id);
+        let choices = match &self.content {
+            McQuestion { options, .. } => options.len(),
+            McQuestionVert { options, other } => options.len() + *other as usize,
+            TextAreaQuestion if answer.is_none() => return Err(format!("{}: an answer is required, if only empty", self.id)),
+            _ => return Ok(()),
+        };
+        match answer {
+            Some(answer) if !answer.parse::<usize>().is_ok_and(|n| n < choices) => {
+                Err(format!("{}: {:?} is not the index of one of the {} options", self.id, answer, choices))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The answer to store from the form values for the question, if any, once `validate` accepts
+    /// them.
+    pub fn convert(&self, resp: &HashMap<String, String>) -> Result<Option<Value>, String> {
+        use QuestionContent::*;
+        self.validate(resp)?;
+        let choice: Option<usize> = resp.get(&self.id).and_then(|answer| answer.parse().ok());
+        Ok(match &self.content {
             Header { .. } | Paragraph { .. } | AlignText { .. } => None,
+            McQuestion { options, expected: Some(expected) } => {
+                let answer = choice.filter(|n_opt| *n_opt < options.len());
+                Some(json!({"attention": true, "passed": answer == Some(*expected), "ord": answer}))
+            }
             McQuestion { options, .. } => {
-                if let Some(answer) = resp.get(&self.id) {
-                    let n_opt: usize = answer.parse().unwrap();
-                    Some(json!({"ord": n_opt, "nom": options[n_opt].clone()}))
-                }
-                else { None }
+                choice.map(|n_opt| json!({"ord": n_opt, "nom": options[n_opt].clone()}))
             }
             McQuestionVert { options, .. } => {
-                if let Some(answer) = resp.get(&self.id) {
-                    let n_opt: usize = answer.parse().unwrap();
+                choice.map(|n_opt| {
                     if n_opt == options.len() {
-                        Some(json!({"nom": "Other", "answer": resp.get(&format!("{:?}.other", &self.id))}))
+                        json!({"nom": "Other", "answer": resp.get(&format!("{}.other", &self.id))})
                     }
                     else {
-                        Some(json!({"ord": n_opt, "nom": options[n_opt].clone()}))
+                        json!({"ord": n_opt, "nom": options[n_opt].clone()})
                     }
-                }
-                else { None }
+                })
             }
             CheckboxQuestion { text } => {
                 if let Some(answer) = resp.get(&self.id) {
@@ -93,16 +270,39 @@ impl Question {
             TextAreaQuestion => {
                 Some(json!({"answer": resp[&self.id].clone()}))
             }
+        })
+    }
+
+    /// The form values `convert` made the stored answer from, by field name, to fill the question
+    /// in again. Attention checks are filled in with the option chosen, which they store along
+    /// with whether they were passed.
+    pub fn form_values(&self, answer: &Value) -> Vec<(String, Value)> {
+        use QuestionContent::*;
+        match &self.content {
+            McQuestion { .. } | McQuestionVert { .. } if answer["ord"].is_u64() => {
+                vec![(self.id.clone(), answer["ord"].clone())]
+            }
+            McQuestionVert { options, .. } if answer["nom"] == "Other" => {
+                let mut values = vec![(self.id.clone(), json!(options.len()))];
+                if let Some(other) = answer.get("answer").filter(|other| other.is_string()) {
+                    values.push((format!("{}.other", self.id), other.clone()));
+                }
+                values
+            }
+            CheckboxQuestion { .. } => vec![(self.id.clone(), answer["checked"].clone())],
+            TextAreaQuestion if answer["answer"].is_string() => vec![(self.id.clone(), answer["answer"].clone())],
+            _ => vec![],
         }
     }
 }
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub enum FeedbackItem {
     Title { text: String },
     Paragraph { text: String },
     Bar { score: f64, min: f64, max: f64 },
     Score { eval: Scorer },
+    ScaleScore { scale: String },
 }
 pub struct Scorer(Box<dyn Send + Sync + Fn(&Value) -> FeedbackItem>);
 
@@ -112,21 +312,33 @@ impl Serialize for Scorer {
     }
 }
 
+impl JsonSchema for Scorer {
+    fn schema_name() -> String {
+        "Scorer".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
 impl FeedbackItem {
-    pub(crate) fn score(&self, value: &Value) -> FeedbackItem {
+    /// The part of the feedback shown for a response, if it has one: a scale score is left out
+    /// when none of the scale's items were answered.
+    pub(crate) fn score(&self, test: &Test, value: &Value) -> Option<FeedbackItem> {
         use FeedbackItem::*;
-        match self {
+        Some(match self {
             Score { eval: Scorer(eval) } => eval(value),
+            ScaleScore { scale } => test.score_scale(scale, value)?,
             Title { text } => Title { text: text.clone() },
             Paragraph { text } => Paragraph { text: text.clone() },
             Bar { score, min, max } => Bar { score: *score, min: *min, max: *max },
-        }
+        })
     }
 }
 
-pub fn make_tipi_test() -> Test {
-    use FeedbackItem::*;
-    let likert7 = vec![
+fn likert7() -> Vec<String> {
+    vec![
         "Disagree strongly".into(),
         "Disagree moderately".into(),
         "Disagree a little".into(),
@@ -134,9 +346,28 @@ pub fn make_tipi_test() -> Test {
         "Agree a little".into(),
         "Agree moderately".into(),
         "Agree strongly".into()
-    ];
+    ]
+}
+
+// The TIPI's items as (id, self-report text, other-report text).
+const TIPI_ITEMS: [(&str, &str, &str); 10] = [
+    ("ep", "Extraverted, enthusiastic", "They are extraverted, enthusiastic"),
+    ("am", "Critical, quarrelsome", "They are critical, quarrelsome"),
+    ("cp", "Dependable, self-disciplined", "They are dependable, self-disciplined"),
+    ("np", "Anxious, easily upset", "They are anxious, easily upset"),
+    ("op", "Open to new experiences, complex", "They are open to new experiences, complex"),
+    ("em", "Reserved, quiet", "They are reserved, quiet"),
+    ("ap", "Sympathetic, warm", "They are sympathetic, warm"),
+    ("cm", "Disorganized, careless", "They are disorganized, careless"),
+    ("nm", "Calm, emotionally stable", "They are calm, emotionally stable"),
+    ("om", "Conventional, uncreative", "They are conventional, uncreative"),
+];
+
+// The TIPI's items with the attention check, worded for a self-report or for an other-report.
+fn tipi_items(other_report: bool) -> Vec<Question> {
+    let likert7 = likert7();
     let mut test_items = vec![];
-    let mut add_item = |id: &str, label: &str| {
+    let mut add_item = |id: &str, label: &str, expected: Option<usize>| {
         use QuestionContent::*;
         test_items.push(Question {
             id: "".into(),
@@ -148,19 +379,51 @@ pub fn make_tipi_test() -> Test {
             id: id.into(),
             content: McQuestion {
                 options: likert7.clone(),
+                expected,
             }
         });
     };
-    add_item("ep", "Extraverted, enthusiastic");
-    add_item("am", "Critical, quarrelsome");
-    add_item("cp", "Dependable, self-disciplined");
-    add_item("np", "Anxious, easily upset");
-    add_item("op", "Open to new experiences, complex");
-    add_item("em", "Reserved, quiet");
-    add_item("ap", "Sympathetic, warm");
-    add_item("cm", "Disorganized, careless");
-    add_item("nm", "Calm, emotionally stable");
-    add_item("om", "Conventional, uncreative");
+    for (id, own, other) in TIPI_ITEMS {
+        add_item(id, if other_report { other } else { own }, None);
+    }
+    add_item("attention", "Please select \"Agree strongly\" for this item", Some(6));
+    test_items
+}
+
+// The TIPI's scales as (name, positively keyed item, negatively keyed item, description).
+const TIPI_SCALES: [(&str, &str, &str, &str); 5] = [
+    ("Extraversion", "ep", "em",
+     "Extraversion is characterized by warmth, gregariousness, assertiveness, \
+     activity, excitement seeking, and positive emotions."),
+    ("Agreeableness", "ap", "am",
+     "Agreeableness is characterized by trust, straightforwardness, altruism, \
+     compliance, modesty and tender-mindedness."),
+    ("Conscientiousness", "cp", "cm",
+     "Conscientiousness is characterized by competence, orderliness, dutifulness, \
+     achievement-striving, self-discipline and deliberation."),
+    ("Neuroticism", "np", "nm",
+     "Neuroticism is characterized by anxiety, anger, depression, \
+     self-consciousness, impulsiveness and vulnerability."),
+    ("Openness", "op", "om",
+     "Openness is characterized by fantasy, aesthetic interests, depth of feelings, \
+     adventurousness, intellectual interests, and liberalism."),
+];
+
+fn tipi_scale(label: &str, pos: &str, neg: &str) -> Scale {
+    Scale {
+        id: label.to_lowercase(),
+        name: label.into(),
+        categories: 7,
+        items: vec![
+            ScaleItem { id: pos.into(), reversed: false },
+            ScaleItem { id: neg.into(), reversed: true },
+        ],
+    }
+}
+
+pub fn make_tipi_test() -> Test {
+    use FeedbackItem::*;
+    let mut test_items = tipi_items(false);
     let mut test = {
         use QuestionContent::*;
         Test {
@@ -169,6 +432,7 @@ pub fn make_tipi_test() -> Test {
             pages: vec![
                 TestPage {
                     condition: Condition::Always,
+                    adaptive: None,
                     elements: {
                         test_items.insert(0, Question {
                             id: "".into(),
@@ -192,6 +456,7 @@ pub fn make_tipi_test() -> Test {
                 },
                 TestPage {
                     condition: Condition::Always,
+                    adaptive: None,
                     elements: vec![
                         Question {
                             id: "".into(),
@@ -233,6 +498,7 @@ pub fn make_tipi_test() -> Test {
                 },
                 TestPage {
                     condition: Condition::Question { id: "additional".into(), value: json!({ "checked": true }) },
+                    adaptive: None,
                     elements: vec![
                         Question {
                             id: "".into(),
@@ -267,6 +533,7 @@ pub fn make_tipi_test() -> Test {
                 },
                 TestPage {
                     condition: Condition::Always,
+                    adaptive: None,
                     elements: vec![
                         Question {
                             id: "".into(),
@@ -313,40 +580,126 @@ pub fn make_tipi_test() -> Test {
                     ],
                 }],
             feedback: vec![],
+            scales: vec![],
+            calibration: Calibration::load("tipi"),
+            consent: Some("consent".into()),
+            private: vec!["comments".into()],
+            demographics: vec!["gender".into()],
+            informant: Some(Informant { test: "tipi-informant".into(), min_reports: 3 }),
+            informant_of: None,
         }
     };
-    let mut add_score = |label: &str, pos: &'static str, neg: &'static str, descr: &str| {
+    for (label, pos, neg, descr) in TIPI_SCALES {
+        let scale = tipi_scale(label, pos, neg);
         test.feedback.push(Title { text: label.into() });
         test.feedback.push(Paragraph { text: descr.into() });
-        test.feedback.push(Score { eval: Scorer(Box::new(move |resp: &Value| {
-            let num_pos: f64 = resp[pos]["ord"].as_f64().unwrap();
-            let num_neg: f64 = resp[neg]["ord"].as_f64().unwrap();
-            Bar { score: 1.0 + (num_pos + (6.0 - num_neg)) / 2.0, min: 1.0, max: 7.0 }
-        }))})
-    };
-    add_score("Extraversion", "ep", "em",
-              "Extraversion is characterized by warmth, gregariousness, assertiveness, \
-              activity, excitement seeking, and positive emotions.");
-    add_score("Agreeableness", "ap", "am",
-              "Agreeableness is characterized by trust, straightforwardness, altruism, \
-              compliance, modesty and tender-mindedness.");
-    add_score("Conscientiousness", "cp", "cm",
-              "Conscientiousness is characterized by competence, orderliness, dutifulness, \
-              achievement-striving, self-discipline and deliberation.");
-    add_score("Neuroticism", "np", "nm",
-              "Neuroticism is characterized by anxiety, anger, depression, \
-              self-consciousness, impulsiveness and vulnerability.");
-    add_score("Openness", "np", "nm",
-              "Openness is characterized by fantasy, aesthetic interests, depth of feelings, \
-              adventurousness, intellectual interests, and liberalism.");
+        test.feedback.push(ScaleScore { scale: scale.id.clone() });
+        test.scales.push(scale);
+    }
     test
 }
 
+pub fn make_tipi_informant_test() -> Test {
+    use QuestionContent::*;
+    let mut test_items = tipi_items(true);
+    test_items.insert(0, Question {
+        id: "".into(),
+        content: Header {
+            title: "TIPI Personality Test: Describe Someone You Know".into(),
+            size: 1,
+        }
+    });
+    test_items.insert(1, Question {
+        id: "".into(),
+        content: Paragraph {
+            text: "Someone you know has asked you to describe their personality. Here are a number \
+            of personality traits that may or may not apply to them. Please select an option for \
+            each statement to indicate the extent to which you agree or disagree that it describes \
+            them. You should rate the extent to which the pair of traits applies to this person, \
+            even if one characteristic applies more strongly than the other. They will only see \
+            the average of several people's answers, never yours alone.".into()
+        }
+    });
+    Test {
+        id: "tipi-informant".into(),
+        name: "Ten Item Personality Inventory (Other-Report)".into(),
+        pages: vec![
+            TestPage {
+                condition: Condition::Always,
+                adaptive: None,
+                elements: test_items,
+            },
+            TestPage {
+                condition: Condition::Always,
+                adaptive: None,
+                elements: vec![
+                    Question {
+                        id: "".into(),
+                        content: Header {
+                            title: "About You".into(),
+                            size: 1,
+                        }
+                    },
+                    Question {
+                        id: "".into(),
+                        content: Header {
+                            title: "How well do you know this person?".into(),
+                            size: 2,
+                        }
+                    },
+                    Question {
+                        id: "acquaintance".into(),
+                        content: McQuestionVert {
+                            options: vec!["Very well".into(), "Fairly well".into(), "Somewhat".into(), "Not very well".into()],
+                            other: false
+                        },
+                    },
+                    Question {
+                        id: "".into(),
+                        content: Header {
+                            title: "How do you know them?".into(),
+                            size: 2,
+                        }
+                    },
+                    Question {
+                        id: "relationship".into(),
+                        content: McQuestionVert {
+                            options: vec!["Friend".into(), "Partner".into(), "Family member".into(), "Colleague".into()],
+                            other: true
+                        },
+                    },
+                    Question {
+                        id: "consent".into(),
+                        content: CheckboxQuestion {
+                            text: "My response may anonymously be entered into public datasets to \
+                               support future research".into(),
+                        }
+                    },
+                ],
+            }],
+        feedback: vec![
+            FeedbackItem::Title { text: "Thank You".into() },
+            FeedbackItem::Paragraph {
+                text: "Your description has been recorded. Once enough people have described them, \
+                the person who invited you will see how others see them on average.".into()
+            },
+        ],
+        scales: TIPI_SCALES.iter().map(|(label, pos, neg, _)| tipi_scale(label, pos, neg)).collect(),
+        calibration: None,
+        consent: Some("consent".into()),
+        private: vec![],
+        demographics: vec![],
+        informant: None,
+        informant_of: Some("tipi".into()),
+    }
+}
+
 pub struct Tests(HashMap<String, Test>);
 
 pub fn make_tests() -> Tests {
     let mut tests = HashMap::new();
     tests.insert("tipi".into(), make_tipi_test());
+    tests.insert("tipi-informant".into(), make_tipi_informant_test());
     Tests(tests)
 }
 
@@ -354,6 +707,17 @@ lazy_static! {
     static ref TESTS: Tests = make_tests();
 }
 
+pub fn get_test(id: &str) -> Option<&'static Test> {
+    TESTS.0.get(id)
+}
+
+/// All tests, by id.
+pub fn all_tests() -> Vec<&'static Test> {
+    let mut tests: Vec<&Test> = TESTS.0.values().collect();
+    tests.sort_by(|a, b| a.id.cmp(&b.id));
+    tests
+}
+
 impl<'a> FromParam<'a> for &Test {
     type Error = Infallible;
 
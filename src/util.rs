@@ -1,4 +1,7 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde_json::Value;
+use time::{Date, OffsetDateTime};
+use time::macros::format_description;
 
 pub fn contains(a: &Value, b: &Value) -> bool {
     use Value::*;
@@ -25,20 +28,13 @@ pub fn contains(a: &Value, b: &Value) -> bool {
         (Object(obj), Object(val)) => {
             let mut each = true;
             for (elk, elv) in obj {
-                println!("Searching {:?}:{:?}", elk, elv);
                 let mut some = false;
                 for (itk, itv) in val {
-                    println!("Testing {:?}:{:?} vs {:?}:{:?}", elk, elv, itk, itv);
                     if elk == itk && contains(elv, itv) {
-                        println!("Found!");
                         some = true;
                     }
-                    else {
-                        println!("Nope.");
-                    }
                 }
                 if !some {
-                    println!("Failed search");
                     each = false;
                 }
             }
@@ -46,4 +42,44 @@ pub fn contains(a: &Value, b: &Value) -> bool {
         }
         _ => false
     }
-}
\ No newline at end of file
+}
+/// Whether `s` is a date in the form YYYY-MM-DD that exists in the calendar.
+pub fn is_iso_date(s: &str) -> bool {
+    Date::parse(s, format_description!("[year]-[month]-[day]")).is_ok()
+}
+
+/// The time in UTC that is `epoch` seconds after the Unix epoch, to the second.
+pub fn date_time(epoch: f64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(epoch.floor() as i64).unwrap()
+}
+
+/// Formats seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS".
+pub fn format_timestamp(epoch: f64) -> String {
+    date_time(epoch).format(format_description!("[year]-[month]-[day] [hour]:[minute]:[second]")).unwrap()
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso_dates() {
+        assert!(is_iso_date("2024-02-29"));
+        assert!(is_iso_date("1999-12-31"));
+        for s in ["", "2024-1-05", "24-01-05", "2024/01/05", "2024-01-05T00:00", "2024-00-10", "2024-13-01", "2024-01-32", "2024-01-00", "abcd-ef-gh", "+202-01-05", "2023-02-29", "2024-02-30", "1900-02-29", "2024-04-31"] {
+            assert!(!is_iso_date(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0.0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951825600.5), "2000-02-29 12:00:00");
+        assert_eq!(format_timestamp(-1.0), "1969-12-31 23:59:59");
+    }
+}
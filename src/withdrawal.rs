@@ -0,0 +1,76 @@
+use sqlx::Postgres;
+use sqlx::pool::PoolConnection;
+use uuid::Uuid;
+use crate::database;
+use crate::signing;
+
+// Respondents can withdraw a response they gave, either by revoking their consent, which keeps
+// the response for their own feedback but leaves it out of every export and computation over
+// the sample, or by deleting it. They reach the withdrawal page through a code to write down or
+// a signed link, both shown with their feedback. Every withdrawal is recorded in an audit table.
+
+const CODE_PURPOSE: &str = "withdrawal-code";
+const LINK_PURPOSE: &str = "withdrawal-link";
+
+#[derive(FromFormField, Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Revoke,
+    Delete,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Revoke => "revoke",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+pub fn link_signature(response_id: Uuid) -> String {
+    signing::sign(LINK_PURPOSE, &response_id.to_string())
+}
+
+pub fn verify_link(response_id: Uuid, signature: &str) -> bool {
+    signing::verify(LINK_PURPOSE, &response_id.to_string(), signature)
+}
+
+/// Makes the response's withdrawal code usable and returns it.
+pub async fn issue_code(response_id: Uuid, conn: &mut PoolConnection<Postgres>) -> String {
+    let code = signing::code(CODE_PURPOSE, &response_id.to_string());
+    database::set_withdrawal_hash(response_id, &signing::code_hash(&code), conn).await;
+    code
+}
+
+pub async fn find_by_code(code: &str, conn: &mut PoolConnection<Postgres>) -> Option<Uuid> {
+    database::find_by_withdrawal_hash(&signing::code_hash(code), conn).await
+}
+
+/// Carries out and records a withdrawal; `via` says whether the respondent came through their
+/// code or a link.
+pub async fn withdraw(response_id: Uuid, action: Action, via: &str, conn: &mut PoolConnection<Postgres>) {
+    let (test_id, _) = match database::get_withdrawal_state(response_id, conn).await {
+        Some(state) => state,
+        None => return,
+    };
+    match action {
+        Action::Revoke => database::withdraw_consent(response_id, conn).await,
+        Action::Delete => database::delete_response(response_id, conn).await,
+    }
+    database::record_withdrawal(response_id, test_id.as_deref(), action.name(), via, conn).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_verify_only_for_their_response() {
+        let signature = link_signature(Uuid::from_u128(1));
+        assert!(verify_link(Uuid::from_u128(1), &signature));
+        assert!(!verify_link(Uuid::from_u128(2), &signature));
+        // The code is signed for another purpose, so it doesn't open the link.
+        let code = signing::code(CODE_PURPOSE, &Uuid::from_u128(1).to_string()).replace('-', "").to_lowercase();
+        assert!(!verify_link(Uuid::from_u128(1), &code));
+    }
+}